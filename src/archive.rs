@@ -0,0 +1,555 @@
+use crate::util::crc32;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+const ZIP_LOCAL_HEADER: u32 = 0x04034B50;
+const ZIP_CENTRAL_HEADER: u32 = 0x02014B50;
+const ZIP_END_OF_DIRECTORY: u32 = 0x06054B50;
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+const ROM_EXTENSIONS: [&str; 3] = ["nes", "fds", "nsf"];
+
+#[inline]
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[inline]
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..(offset + 2))?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+#[inline]
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..(offset + 4))?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+impl<'a> BitReader<'a> {
+    const fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn bits(&mut self, count: u32) -> Option<u32> {
+        while self.bit_count < count {
+            let byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+
+        let value = self.bit_buffer & ((1u32 << count) - 1);
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Some(value)
+    }
+
+    /// Discards the remaining bits of the current byte
+    #[inline]
+    fn align(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+}
+
+/// Canonical Huffman code as used by DEFLATE
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &length in lengths.iter() {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for i in 1..16 {
+            offsets[i] = offsets[i - 1] + counts[i - 1];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Option<u16> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for &count in self.counts[1..].iter() {
+            code |= reader.bits(1)? as i32;
+            let count = count as i32;
+            if (code - first) < count {
+                return self.symbols.get((index + code - first) as usize).copied();
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> Option<()> {
+    const LENGTH_BASE: [u16; 29] = [
+        3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+        131, 163, 195, 227, 258,
+    ];
+    const LENGTH_EXTRA: [u8; 29] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+    ];
+    const DISTANCE_BASE: [u16; 30] = [
+        1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+        2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+    ];
+    const DISTANCE_EXTRA: [u8; 30] = [
+        0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12,
+        13, 13,
+    ];
+
+    loop {
+        let symbol = lengths.decode(reader)? as usize;
+        if symbol < 256 {
+            output.push(symbol as u8);
+        } else if symbol == 256 {
+            return Some(());
+        } else {
+            let symbol = symbol - 257;
+            let length = (*LENGTH_BASE.get(symbol)? as usize)
+                + (reader.bits(LENGTH_EXTRA[symbol] as u32)? as usize);
+
+            let symbol = distances.decode(reader)? as usize;
+            let distance = (*DISTANCE_BASE.get(symbol)? as usize)
+                + (reader.bits(DISTANCE_EXTRA[symbol] as u32)? as usize);
+            if distance > output.len() {
+                return None;
+            }
+
+            // The copy may overlap with the bytes it produces
+            let start = output.len() - distance;
+            for i in 0..length {
+                let byte = output[start + i];
+                output.push(byte);
+            }
+        }
+    }
+}
+
+fn read_dynamic_codes(reader: &mut BitReader) -> Option<(Huffman, Huffman)> {
+    const CODE_LENGTH_ORDER: [usize; 19] = [
+        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+    ];
+
+    let length_count = (reader.bits(5)? as usize) + 257;
+    let distance_count = (reader.bits(5)? as usize) + 1;
+    let code_length_count = (reader.bits(4)? as usize) + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in CODE_LENGTH_ORDER[..code_length_count].iter() {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![0u8; length_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => (*lengths.get(index.checked_sub(1)?)?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            18 => (0, 11 + reader.bits(7)?),
+            _ => return None,
+        };
+
+        for _ in 0..repeat {
+            *lengths.get_mut(index)? = value;
+            index += 1;
+        }
+    }
+
+    Some((
+        Huffman::new(&lengths[..length_count]),
+        Huffman::new(&lengths[length_count..]),
+    ))
+}
+
+/// Decompresses raw DEFLATE data
+fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+
+    loop {
+        let last = reader.bits(1)? != 0;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let length = read_u16(reader.data, reader.pos)? as usize;
+                let bytes = reader
+                    .data
+                    .get((reader.pos + 4)..(reader.pos + 4 + length))?;
+                output.extend_from_slice(bytes);
+                reader.pos += 4 + length;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].iter_mut().for_each(|length| *length = 8);
+                lengths[144..256].iter_mut().for_each(|length| *length = 9);
+                lengths[256..280].iter_mut().for_each(|length| *length = 7);
+                lengths[280..].iter_mut().for_each(|length| *length = 8);
+                let fixed_lengths = Huffman::new(&lengths);
+                let fixed_distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut reader, &mut output, &fixed_lengths, &fixed_distances)?;
+            }
+            2 => {
+                let (lengths, distances) = read_dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &lengths, &distances)?;
+            }
+            _ => return None,
+        }
+
+        if last {
+            return Some(output);
+        }
+    }
+}
+
+fn is_rom_name(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| {
+            ROM_EXTENSIONS
+                .iter()
+                .any(|rom_extension| extension.eq_ignore_ascii_case(rom_extension))
+        })
+        .unwrap_or(false)
+}
+
+/// Extracts the named entry or the first ROM file from a zip archive
+fn read_zip(data: &[u8], entry: Option<&str>) -> Result<Vec<u8>, Error> {
+    // The end of central directory record is followed by a comment of up to 64k
+    let search_start = data.len().saturating_sub(22 + 0xFFFF);
+    let end_offset = (search_start..data.len().saturating_sub(21))
+        .rev()
+        .find(|&offset| read_u32(data, offset) == Some(ZIP_END_OF_DIRECTORY))
+        .ok_or_else(|| invalid_data("Zip directory not found"))?;
+
+    let entry_count = read_u16(data, end_offset + 10).unwrap_or(0);
+    let mut offset = read_u32(data, end_offset + 16).unwrap_or(0) as usize;
+    for _ in 0..entry_count {
+        if read_u32(data, offset) != Some(ZIP_CENTRAL_HEADER) {
+            return Err(invalid_data("Corrupted zip directory"));
+        }
+
+        let header = || -> Option<(u16, u32, u32, usize, usize, &[u8])> {
+            let method = read_u16(data, offset + 10)?;
+            let crc = read_u32(data, offset + 16)?;
+            let compressed_size = read_u32(data, offset + 20)?;
+            let name_length = read_u16(data, offset + 28)? as usize;
+            let extra_length = read_u16(data, offset + 30)? as usize;
+            let comment_length = read_u16(data, offset + 32)? as usize;
+            let local_offset = read_u32(data, offset + 42)? as usize;
+            let name = data.get((offset + 46)..(offset + 46 + name_length))?;
+            let next = offset + 46 + name_length + extra_length + comment_length;
+            Some((method, crc, compressed_size, local_offset, next, name))
+        };
+        let (method, crc, compressed_size, local_offset, next, name) =
+            header().ok_or_else(|| invalid_data("Corrupted zip directory"))?;
+        offset = next;
+
+        let name = String::from_utf8_lossy(name);
+        let matches = match entry {
+            Some(entry) => name == entry,
+            None => is_rom_name(&name),
+        };
+        if !matches {
+            continue;
+        }
+
+        if read_u32(data, local_offset) != Some(ZIP_LOCAL_HEADER) {
+            return Err(invalid_data("Corrupted zip entry"));
+        }
+        let name_length = read_u16(data, local_offset + 26).unwrap_or(0) as usize;
+        let extra_length = read_u16(data, local_offset + 28).unwrap_or(0) as usize;
+        let start = local_offset + 30 + name_length + extra_length;
+        let compressed = data
+            .get(start..(start + compressed_size as usize))
+            .ok_or_else(|| invalid_data("Truncated zip entry"))?;
+
+        let contents = match method {
+            0 => compressed.to_vec(),
+            8 => inflate(compressed).ok_or_else(|| invalid_data("Corrupted zip entry"))?,
+            _ => return Err(invalid_data("Unsupported zip compression method")),
+        };
+        if crc32(0, &contents) != crc {
+            return Err(invalid_data("Zip entry checksum mismatch"));
+        }
+        return Ok(contents);
+    }
+
+    Err(Error::new(
+        ErrorKind::NotFound,
+        "No ROM found in zip archive",
+    ))
+}
+
+fn read_gzip(data: &[u8]) -> Result<Vec<u8>, Error> {
+    const FLAG_HEADER_CRC: u8 = 0x02;
+    const FLAG_EXTRA: u8 = 0x04;
+    const FLAG_NAME: u8 = 0x08;
+    const FLAG_COMMENT: u8 = 0x10;
+
+    let skip_string = |offset: usize| -> Option<usize> {
+        let length = data.get(offset..)?.iter().position(|&byte| byte == 0)?;
+        Some(offset + length + 1)
+    };
+    let body_offset = || -> Option<usize> {
+        if *data.get(2)? != 8 {
+            return None;
+        }
+
+        let flags = *data.get(3)?;
+        let mut offset = 10;
+        if (flags & FLAG_EXTRA) != 0 {
+            offset += 2 + (read_u16(data, offset)? as usize);
+        }
+        if (flags & FLAG_NAME) != 0 {
+            offset = skip_string(offset)?;
+        }
+        if (flags & FLAG_COMMENT) != 0 {
+            offset = skip_string(offset)?;
+        }
+        if (flags & FLAG_HEADER_CRC) != 0 {
+            offset += 2;
+        }
+        Some(offset)
+    };
+
+    let offset = body_offset().ok_or_else(|| invalid_data("Invalid gzip header"))?;
+    let body = data
+        .get(offset..data.len().saturating_sub(8))
+        .ok_or_else(|| invalid_data("Truncated gzip file"))?;
+    let contents = inflate(body).ok_or_else(|| invalid_data("Corrupted gzip file"))?;
+
+    let crc = read_u32(data, data.len() - 8);
+    if crc != Some(crc32(0, &contents)) {
+        return Err(invalid_data("Gzip checksum mismatch"));
+    }
+    Ok(contents)
+}
+
+/// Reads a file, transparently extracting it if it is a zip or gzip archive
+///
+/// From zip archives either the entry called `entry` or the first ROM file is read.
+pub fn read_file<P: AsRef<Path>>(file: P, entry: Option<&str>) -> Result<Vec<u8>, Error> {
+    let data = std::fs::read(file)?;
+    if read_u32(&data, 0) == Some(ZIP_LOCAL_HEADER) {
+        read_zip(&data, entry)
+    } else if data.starts_with(&GZIP_MAGIC) {
+        read_gzip(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &[u8] = b"The quick brown fox jumps over the lazy dog. \
+        The quick brown fox jumps over the lazy dog. \
+        Pack my box with five dozen liquor jugs! 0123456789 \
+        SPHINX OF BLACK QUARTZ, JUDGE MY VOW. \
+        SPHINX OF BLACK QUARTZ, JUDGE MY VOW. \
+        SPHINX OF BLACK QUARTZ, JUDGE MY VOW. ";
+
+    // TEXT compressed by zlib with fixed and with dynamic Huffman codes
+    const FIXED: [u8; 136] = [
+        0x0B, 0xC9, 0x48, 0x55, 0x28, 0x2C, 0xCD, 0x4C, 0xCE, 0x56, 0x48, 0x2A, 0xCA, 0x2F, 0xCF,
+        0x53, 0x48, 0xCB, 0xAF, 0x50, 0xC8, 0x2A, 0xCD, 0x2D, 0x28, 0x56, 0xC8, 0x2F, 0x4B, 0x2D,
+        0x52, 0x28, 0x01, 0x4A, 0xE7, 0x24, 0x56, 0x55, 0x2A, 0xA4, 0xE4, 0xA7, 0xEB, 0x29, 0x84,
+        0x90, 0xA2, 0x38, 0x20, 0x11, 0xA8, 0x2E, 0xB7, 0x52, 0x21, 0x09, 0xA8, 0xA8, 0x3C, 0xB3,
+        0x24, 0x43, 0x21, 0x2D, 0xB3, 0x2C, 0x15, 0x28, 0x55, 0x95, 0x9A, 0xA7, 0x90, 0x93, 0x59,
+        0x58, 0x9A, 0x5F, 0x04, 0xD4, 0x9B, 0x5E, 0xAC, 0xA8, 0x60, 0x60, 0x68, 0x64, 0x6C, 0x62,
+        0x6A, 0x66, 0x6E, 0x61, 0xA9, 0x10, 0x1C, 0xE0, 0xE1, 0xE9, 0x17, 0xA1, 0xE0, 0xEF, 0xA6,
+        0xE0, 0xE4, 0xE3, 0xE8, 0xEC, 0xAD, 0x10, 0x18, 0xEA, 0x18, 0x14, 0x12, 0xA5, 0xA3, 0xE0,
+        0x15, 0xEA, 0xE2, 0xEE, 0xAA, 0xE0, 0x1B, 0xA9, 0x10, 0xE6, 0x1F, 0xAE, 0x47, 0x55, 0x55,
+        0x00,
+    ];
+    const DYNAMIC: [u8; 133] = [
+        0xAD, 0xCB, 0x47, 0x12, 0x82, 0x30, 0x00, 0x46, 0xE1, 0xAB, 0xFC, 0xEE, 0x1D, 0xC6, 0x5E,
+        0x96, 0x88, 0xBD, 0x81, 0x1A, 0x6C, 0x3B, 0xD0, 0x00, 0x51, 0x20, 0x12, 0x08, 0x08, 0xA7,
+        0x37, 0x57, 0x70, 0x86, 0xF5, 0xFB, 0x1E, 0x09, 0x28, 0x12, 0xC9, 0x1E, 0x6F, 0xB8, 0x82,
+        0x17, 0x31, 0x3C, 0xFE, 0xC5, 0x4B, 0x46, 0x9F, 0x14, 0x3C, 0xA7, 0x02, 0x99, 0xCA, 0xA1,
+        0x53, 0x95, 0x78, 0x72, 0x5F, 0x03, 0xF9, 0x07, 0x5B, 0x8E, 0x72, 0x51, 0x09, 0x57, 0xA1,
+        0x82, 0x65, 0x01, 0x3C, 0x96, 0x53, 0x95, 0x2A, 0x1A, 0x23, 0x64, 0x89, 0xE4, 0x42, 0xBD,
+        0x7E, 0xDA, 0x40, 0xAB, 0xDD, 0xE9, 0xF6, 0xFA, 0x83, 0xE1, 0x68, 0x8C, 0x93, 0xB5, 0x5C,
+        0xED, 0xAF, 0x30, 0xE7, 0x98, 0x6C, 0x75, 0x63, 0x83, 0x83, 0xAD, 0x1F, 0xC9, 0xBD, 0x89,
+        0xB5, 0x3D, 0x5D, 0xCC, 0xB0, 0xBB, 0xE1, 0x6C, 0x5E, 0xB4, 0x5A, 0xD5, 0x0F,
+    ];
+
+    /// Wraps data in a single stored block
+    fn stored(data: &[u8]) -> Vec<u8> {
+        let length = data.len() as u16;
+        let mut block = vec![0x01];
+        block.extend_from_slice(&length.to_le_bytes());
+        block.extend_from_slice(&(!length).to_le_bytes());
+        block.extend_from_slice(data);
+        block
+    }
+
+    /// Builds a zip archive from entries of name, compression method, compressed data and CRC32
+    fn zip(entries: &[(&str, u16, &[u8], u32)]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut directory = Vec::new();
+        for &(name, method, data, crc) in entries.iter() {
+            let local_offset = archive.len() as u32;
+
+            archive.extend_from_slice(&ZIP_LOCAL_HEADER.to_le_bytes());
+            archive.extend_from_slice(&[20, 0, 0, 0]);
+            archive.extend_from_slice(&method.to_le_bytes());
+            archive.extend_from_slice(&[0; 4]);
+            archive.extend_from_slice(&crc.to_le_bytes());
+            archive.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            // The uncompressed size is not checked
+            archive.extend_from_slice(&[0; 4]);
+            archive.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            archive.extend_from_slice(&[0; 2]);
+            archive.extend_from_slice(name.as_bytes());
+            archive.extend_from_slice(data);
+
+            directory.extend_from_slice(&ZIP_CENTRAL_HEADER.to_le_bytes());
+            directory.extend_from_slice(&[20, 0, 20, 0, 0, 0]);
+            directory.extend_from_slice(&method.to_le_bytes());
+            directory.extend_from_slice(&[0; 4]);
+            directory.extend_from_slice(&crc.to_le_bytes());
+            directory.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            directory.extend_from_slice(&[0; 4]);
+            directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            directory.extend_from_slice(&[0; 12]);
+            directory.extend_from_slice(&local_offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+
+        let directory_offset = archive.len() as u32;
+        archive.extend_from_slice(&directory);
+        archive.extend_from_slice(&ZIP_END_OF_DIRECTORY.to_le_bytes());
+        archive.extend_from_slice(&[0; 4]);
+        archive.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        archive.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        archive.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        archive.extend_from_slice(&directory_offset.to_le_bytes());
+        archive.extend_from_slice(&[0; 2]);
+        archive
+    }
+
+    /// Builds a gzip file that stores the original file name
+    fn gzip(body: &[u8], crc: u32, size: usize) -> Vec<u8> {
+        let mut file = vec![0x1F, 0x8B, 8, 0x08, 0, 0, 0, 0, 0, 0xFF];
+        file.extend_from_slice(b"game.nes\0");
+        file.extend_from_slice(body);
+        file.extend_from_slice(&crc.to_le_bytes());
+        file.extend_from_slice(&(size as u32).to_le_bytes());
+        file
+    }
+
+    #[test]
+    fn inflate_stored_block() {
+        assert_eq!(inflate(&stored(TEXT)).as_deref(), Some(TEXT));
+    }
+
+    #[test]
+    fn inflate_fixed_block() {
+        assert_eq!(inflate(&FIXED).as_deref(), Some(TEXT));
+    }
+
+    #[test]
+    fn inflate_dynamic_block() {
+        assert_eq!(inflate(&DYNAMIC).as_deref(), Some(TEXT));
+    }
+
+    #[test]
+    fn zip_entries() {
+        let notes = b"Not a ROM";
+        let archive = zip(&[
+            ("notes.txt", 0, notes, crc32(0, notes)),
+            ("game.nes", 8, &DYNAMIC, crc32(0, TEXT)),
+        ]);
+
+        assert_eq!(read_zip(&archive, None).unwrap(), TEXT);
+        assert_eq!(read_zip(&archive, Some("notes.txt")).unwrap(), notes);
+        assert_eq!(
+            read_zip(&archive, Some("other.nes")).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn zip_checksum_mismatch() {
+        let archive = zip(&[("game.nes", 8, &FIXED, crc32(0, TEXT) ^ 1)]);
+        assert_eq!(
+            read_zip(&archive, None).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn gzip_file() {
+        let file = gzip(&DYNAMIC, crc32(0, TEXT), TEXT.len());
+        assert_eq!(read_gzip(&file).unwrap(), TEXT);
+
+        let file = gzip(&DYNAMIC, crc32(0, TEXT) ^ 1, TEXT.len());
+        assert_eq!(read_gzip(&file).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_data() {
+        let blocks = [stored(TEXT), FIXED.to_vec(), DYNAMIC.to_vec()];
+        for block in blocks.iter() {
+            for length in 0..block.len() {
+                assert_eq!(inflate(&block[..length]), None);
+            }
+        }
+
+        let archive = zip(&[("game.nes", 8, &DYNAMIC, crc32(0, TEXT))]);
+        for length in 0..archive.len() {
+            assert!(read_zip(&archive[..length], None).is_err());
+        }
+
+        // The directory is intact but the entry's data ends early
+        let archive = zip(&[("game.nes", 8, &DYNAMIC[..64], crc32(0, TEXT))]);
+        assert!(read_zip(&archive, None).is_err());
+
+        let file = gzip(&DYNAMIC, crc32(0, TEXT), TEXT.len());
+        for length in 0..file.len() {
+            assert!(read_gzip(&file[..length]).is_err());
+        }
+    }
+}
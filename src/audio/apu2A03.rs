@@ -1,9 +1,10 @@
 use crate::audio::*;
 use crate::bus::{AddressRange, Bus};
 use crate::cpu::cpu6502;
+use crate::system::nes::{Cartridge, Region};
 use crate::*;
 
-trait Channel {
+pub(super) trait Channel {
     fn write(&mut self, address: u8, data: u8);
     fn clock(&mut self, quarter: bool, half: bool);
     fn sample(&mut self) -> f32;
@@ -112,21 +113,22 @@ impl Sweep {
         self.update_target_period();
 
         if half {
-            self.divider -= 1;
-            if self.divider == 0 {
-                if (self.shift > 0)
-                    && self.enabled
-                    && self.sequencer.is_pulse_enabled()
-                    && (self.target_period <= 0x07FF)
-                {
-                    self.sequencer.period = self.target_period;
-                }
-                self.divider = self.period;
+            if (self.divider == 0)
+                && (self.shift > 0)
+                && self.enabled
+                && self.sequencer.is_pulse_enabled()
+                && (self.target_period <= 0x07FF)
+            {
+                self.sequencer.period = self.target_period;
             }
 
-            if self.reload {
+            // The divider is reloaded when it reaches 0 instead of counting past it,
+            // so a sweep period of P takes P + 1 half frames
+            if (self.divider == 0) || self.reload {
                 self.divider = self.period;
                 self.reload = false;
+            } else {
+                self.divider -= 1;
             }
         }
 
@@ -165,7 +167,7 @@ impl LengthCounter {
     }
 }
 
-const VOLUME_SCALE: f32 = 15.0;
+pub(super) const VOLUME_SCALE: f32 = 15.0;
 
 struct Envelope {
     length_counter: LengthCounter,
@@ -231,7 +233,7 @@ impl Envelope {
     }
 }
 
-struct PulseChannel {
+pub(super) struct PulseChannel {
     sequence: u8,
     sequence_pos: u8,
     enabled: bool,
@@ -241,7 +243,7 @@ struct PulseChannel {
 impl PulseChannel {
     const SEQUENCES: [u8; 4] = [0b00000001, 0b00000011, 0b00001111, 0b11111100];
 
-    const fn new(is_channel_1: bool) -> Self {
+    pub(super) const fn new(is_channel_1: bool) -> Self {
         Self {
             sequence: Self::SEQUENCES[0],
             sequence_pos: 0,
@@ -250,6 +252,19 @@ impl PulseChannel {
             envelope: Envelope::new(),
         }
     }
+
+    #[inline]
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.envelope.length_counter.counter = 0;
+        }
+    }
+
+    #[inline]
+    pub(super) const fn is_playing(&self) -> bool {
+        self.envelope.length_counter.counter > 0
+    }
 }
 impl Channel for PulseChannel {
     fn write(&mut self, address: u8, data: u8) {
@@ -417,6 +432,7 @@ impl Channel for TriangleChannel {
 }
 
 struct NoiseChannel {
+    region: Region,
     enabled: bool,
     shift: Wrapping<u16>,
     mode: bool,
@@ -426,6 +442,7 @@ struct NoiseChannel {
 impl NoiseChannel {
     const fn new() -> Self {
         Self {
+            region: Region::Ntsc,
             enabled: true,
             shift: Wrapping(0x0001),
             mode: false,
@@ -439,6 +456,9 @@ impl Channel for NoiseChannel {
         const PERIOD_LOOKUP: [u16; 16] = [
             4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
         ];
+        const PERIOD_LOOKUP_PAL: [u16; 16] = [
+            4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+        ];
 
         match address {
             0 => {
@@ -448,8 +468,12 @@ impl Channel for NoiseChannel {
             1 => {}
             2 => {
                 self.mode = (data & 0x80) != 0;
+                let lookup = match self.region {
+                    Region::Ntsc | Region::Dendy => &PERIOD_LOOKUP,
+                    Region::Pal => &PERIOD_LOOKUP_PAL,
+                };
                 self.sequencer
-                    .set_period(PERIOD_LOOKUP[(data & 0x0F) as usize] - 1);
+                    .set_period(lookup[(data & 0x0F) as usize] - 1);
             }
             3 => {
                 self.envelope.length_counter.load(data);
@@ -506,6 +530,7 @@ struct SampleReader<'a> {
     loop_enabled: bool,
     current_pos: cpu6502::Address,
     bytes_remaining: u16,
+    buffer: Option<cpu6502::Word>,
     current: cpu6502::Word,
     bits_remaining: u8,
     output: bool,
@@ -523,6 +548,7 @@ impl<'a> SampleReader<'a> {
             loop_enabled: false,
             current_pos: Wrapping(0xC000),
             bytes_remaining: 0,
+            buffer: None,
             current: Wrapping(0),
             bits_remaining: 0,
             output: false,
@@ -554,7 +580,6 @@ impl<'a> SampleReader<'a> {
         if self.bytes_remaining == 0 {
             self.current_pos = Wrapping(self.address);
             self.bytes_remaining = self.length;
-            self.has_ended = false;
         }
     }
 
@@ -584,27 +609,46 @@ impl<'a> SampleReader<'a> {
         self.has_ended
     }
 
+    /// There are sample bytes left to fetch, this is what $4015 reports
+    #[inline]
+    const fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    /// The sample buffer is empty and there are bytes left to fetch
+    #[inline]
+    fn dma_pending(&self) -> bool {
+        self.buffer.is_none() && (self.bytes_remaining > 0)
+    }
+
+    /// Fills the sample buffer, the CPU has to be halted for this
+    fn fetch(&mut self) {
+        self.buffer = Some(self.bus.borrow().read(self.current_pos));
+        self.current_pos += Wrapping(1);
+        if self.current_pos.0 == 0 {
+            self.current_pos = Wrapping(0x8000);
+        }
+
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.loop_enabled {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
     fn clock(&mut self) {
         if self.bits_remaining == 0 {
             self.bits_remaining = 8;
 
-            if !self.has_ended {
-                if self.bytes_remaining == 0 {
-                    self.has_ended = true;
-
-                    if self.loop_enabled {
-                        self.restart();
-                    } else if self.irq_enabled {
-                        self.irq = true;
-                    }
-                }
-
-                self.current = self.bus.borrow_mut().read(self.current_pos);
-                self.current_pos += Wrapping(1);
-                if self.current_pos.0 == 0 {
-                    self.current_pos = Wrapping(0x8000);
-                }
-                self.bytes_remaining -= 1;
+            // The output is silenced for a whole cycle if the sample buffer is empty
+            if let Some(data) = self.buffer.take() {
+                self.current = data;
+                self.has_ended = false;
+            } else {
+                self.has_ended = true;
             }
         }
 
@@ -615,6 +659,7 @@ impl<'a> SampleReader<'a> {
 }
 
 struct DmcChannel<'a> {
+    region: Region,
     enabled: bool,
     rate: u8,
     output: u8,
@@ -624,6 +669,7 @@ struct DmcChannel<'a> {
 impl<'a> DmcChannel<'a> {
     const fn new(bus: EmuRef<Bus<'a, cpu6502::Address, cpu6502::Word>>) -> Self {
         Self {
+            region: Region::Ntsc,
             enabled: true,
             rate: 0,
             output: 0,
@@ -637,11 +683,18 @@ impl<'a> Channel for DmcChannel<'a> {
         const RATE_LOOKUP: [u8; 16] = [
             214, 190, 170, 160, 143, 127, 113, 107, 95, 80, 71, 64, 53, 42, 36, 27,
         ];
+        const RATE_LOOKUP_PAL: [u8; 16] = [
+            199, 177, 158, 149, 138, 118, 105, 99, 88, 74, 66, 59, 49, 39, 33, 25,
+        ];
 
         match address {
             0 => {
                 self.reader.set_flags(data);
-                self.rate = RATE_LOOKUP[(data & 0x0F) as usize] + 1;
+                let lookup = match self.region {
+                    Region::Ntsc | Region::Dendy => &RATE_LOOKUP,
+                    Region::Pal => &RATE_LOOKUP_PAL,
+                };
+                self.rate = lookup[(data & 0x0F) as usize] + 1;
             }
             1 => {
                 self.output = data & 0x7F;
@@ -694,6 +747,9 @@ pub struct Apu2A03<'a> {
     triangle_channel: TriangleChannel,
     noise_channel: NoiseChannel,
     dmc_channel: DmcChannel<'a>,
+    cartridge: Option<EmuRef<Cartridge>>,
+    region: Region,
+    seconds_per_clock: f32,
     counter_mode: bool,
     even_cycle: bool,
     cycles: u32,
@@ -702,8 +758,6 @@ pub struct Apu2A03<'a> {
     t: f32,
 }
 impl<'a> Apu2A03<'a> {
-    const SECONDS_PER_CLOCK: f32 = 1.0 / (NES_APU_CLOCK as f32);
-
     pub fn new(
         range_start: cpu6502::Address,
         bus: EmuRef<Bus<'a, cpu6502::Address, cpu6502::Word>>,
@@ -723,6 +777,9 @@ impl<'a> Apu2A03<'a> {
             triangle_channel,
             noise_channel,
             dmc_channel,
+            cartridge: None,
+            region: Region::Ntsc,
+            seconds_per_clock: 1.0 / (Region::Ntsc.apu_clock() as f32),
             counter_mode: false,
             even_cycle: false,
             cycles: 0,
@@ -740,6 +797,33 @@ impl<'a> Apu2A03<'a> {
         make_ref(Self::new(range_start, bus))
     }
 
+    #[inline]
+    pub fn set_cartridge(&mut self, cartridge: EmuRef<Cartridge>) {
+        self.cartridge = Some(cartridge);
+    }
+
+    #[inline]
+    pub fn remove_cartridge(&mut self) {
+        self.cartridge = None;
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.seconds_per_clock = 1.0 / (region.apu_clock() as f32);
+        self.noise_channel.region = region;
+        self.dmc_channel.region = region;
+    }
+
+    /// Frame counter steps in APU cycles: quarter, half, three quarter, full 4-step, full 5-step
+    #[inline]
+    const fn frame_steps(&self) -> [u32; 5] {
+        match self.region {
+            // The Dendy APU uses the same tables as NTSC, only its clock is slower
+            Region::Ntsc | Region::Dendy => [3729, 7457, 11186, 14915, 18641],
+            Region::Pal => [4157, 8314, 12470, 16627, 20783],
+        }
+    }
+
     #[inline]
     pub const fn dmc_irq_requested(&self) -> bool {
         self.dmc_channel.reader.irq()
@@ -750,6 +834,18 @@ impl<'a> Apu2A03<'a> {
         self.irq
     }
 
+    /// The DMC wants to halt the CPU to fetch a sample byte
+    #[inline]
+    pub fn dmc_dma_pending(&self) -> bool {
+        self.dmc_channel.reader.dma_pending()
+    }
+
+    /// Performs the read cycle of a DMC DMA
+    #[inline]
+    pub fn dmc_dma(&mut self) {
+        self.dmc_channel.reader.fetch();
+    }
+
     fn clock_one(&mut self, buffer: &mut SampleBuffer) {
         self.even_cycle = !self.even_cycle;
         self.irq = false;
@@ -758,13 +854,14 @@ impl<'a> Apu2A03<'a> {
             self.cycles += 1;
         }
 
+        let steps = self.frame_steps();
         let full = if self.counter_mode {
-            self.cycles == 18641
+            self.cycles == steps[4]
         } else {
-            self.cycles == 14915
+            self.cycles == steps[3]
         };
-        let half = (self.cycles == 7457) || full;
-        let quarter = (self.cycles == 3729) || (self.cycles == 11186) || half;
+        let half = (self.cycles == steps[1]) || full;
+        let quarter = (self.cycles == steps[0]) || (self.cycles == steps[2]) || half;
         if full {
             self.cycles = 0;
             if !self.inhibit_irq && !self.counter_mode {
@@ -781,7 +878,7 @@ impl<'a> Apu2A03<'a> {
             self.noise_channel.clock(quarter, half);
             self.dmc_channel.clock(quarter, half);
 
-            self.t += Self::SECONDS_PER_CLOCK;
+            self.t += self.seconds_per_clock;
             while self.t >= 0.0 {
                 self.t -= SECONDS_PER_SAMPLE;
 
@@ -791,11 +888,18 @@ impl<'a> Apu2A03<'a> {
                 let noise_sample = self.noise_channel.sample();
                 let dmc_sample = self.dmc_channel.sample();
 
+                // Some cartridges contain additional sound hardware that gets mixed in here
+                let expansion_sample = if let Some(cartridge) = &self.cartridge {
+                    cartridge.borrow().audio_sample()
+                } else {
+                    0.0
+                };
+
                 let sample = (0.00752 * (pulse_1_sample + pulse_2_sample))
                     + (0.00851 * triangle_sample)
                     + (0.00494 * noise_sample)
                     + (0.00335 * dmc_sample);
-                buffer.write(sample * VOLUME_SCALE);
+                buffer.write(sample * VOLUME_SCALE + expansion_sample);
             }
         }
     }
@@ -896,7 +1000,7 @@ impl<'a> BusComponent<cpu6502::Address, cpu6502::Word> for Apu2A03Control<'a> {
         if apu_borrow.noise_channel.envelope.length_counter.counter > 0 {
             result |= 0x08
         }
-        if !apu_borrow.dmc_channel.reader.has_ended() {
+        if apu_borrow.dmc_channel.reader.is_active() {
             result |= 0x10
         }
         if apu_borrow.dmc_channel.reader.irq() {
@@ -0,0 +1,93 @@
+use crate::audio::apu2A03::{Channel, PulseChannel, VOLUME_SCALE};
+use crate::audio::Sample;
+
+// The MMC5 has no frame counter, envelopes and length counters are clocked at a fixed 240 Hz.
+// This is the number of APU cycles (every second CPU cycle) per clock.
+const FRAME_PERIOD: u32 = 3728;
+
+/// Expansion audio of the MMC5: two pulse channels without sweep units and a raw PCM channel
+pub struct Mmc5Audio {
+    pulse_channel_1: PulseChannel,
+    pulse_channel_2: PulseChannel,
+    pcm_output: u8,
+    even_cycle: bool,
+    cycles: u32,
+}
+impl Mmc5Audio {
+    pub const fn new() -> Self {
+        Self {
+            pulse_channel_1: PulseChannel::new(false),
+            pulse_channel_2: PulseChannel::new(false),
+            pcm_output: 0,
+            even_cycle: false,
+            cycles: 0,
+        }
+    }
+
+    /// Reads from the audio registers
+    ///
+    /// The address is given relative to $5000
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0x15 => {
+                let mut result: u8 = 0x00;
+                if self.pulse_channel_1.is_playing() {
+                    result |= 0x01;
+                }
+                if self.pulse_channel_2.is_playing() {
+                    result |= 0x02;
+                }
+                result
+            }
+            _ => 0x00,
+        }
+    }
+
+    /// Writes to the audio registers
+    ///
+    /// The address is given relative to $5000
+    pub fn write(&mut self, address: u16, data: u8) {
+        match address {
+            // Register 1 of each pulse channel would control the missing sweep unit
+            0x01 | 0x05 => {}
+            0x00..=0x03 => self.pulse_channel_1.write(address as u8, data),
+            0x04..=0x07 => self.pulse_channel_2.write((address - 0x04) as u8, data),
+            0x11 => {
+                // Only PCM write mode is supported since no known game uses read mode.
+                // Writing zero has no effect on the output.
+                if data != 0 {
+                    self.pcm_output = data;
+                }
+            }
+            0x15 => {
+                self.pulse_channel_1.set_enabled((data & 0x01) != 0);
+                self.pulse_channel_2.set_enabled((data & 0x02) != 0);
+            }
+            _ => {}
+        }
+    }
+
+    /// Advances the channels by one CPU cycle
+    pub fn clock(&mut self) {
+        self.even_cycle = !self.even_cycle;
+        if self.even_cycle {
+            self.cycles += 1;
+            let frame = self.cycles == FRAME_PERIOD;
+            if frame {
+                self.cycles = 0;
+            }
+
+            self.pulse_channel_1.clock(frame, frame);
+            self.pulse_channel_2.clock(frame, frame);
+        }
+    }
+
+    pub fn sample(&mut self) -> Sample {
+        let pulse_1_sample = self.pulse_channel_1.sample();
+        let pulse_2_sample = self.pulse_channel_2.sample();
+        let pcm_sample = ((self.pcm_output >> 1) as f32) / VOLUME_SCALE;
+
+        // Mixed at the same levels as the corresponding channels of the 2A03
+        ((0.00752 * (pulse_1_sample + pulse_2_sample)) + (0.00335 * pcm_sample)) * VOLUME_SCALE
+    }
+}
@@ -1,5 +1,10 @@
 #[allow(non_snake_case)]
 pub mod apu2A03;
+pub mod mmc5;
+pub mod namco163;
+pub mod sunsoft5b;
+pub mod vrc6;
+pub mod vrc7;
 
 use crate::bus::BusComponent;
 use crate::types::HardwareInteger;
@@ -0,0 +1,146 @@
+use crate::audio::Sample;
+
+// The chip only updates one channel at a time, each update takes 15 CPU cycles
+const CHANNEL_PERIOD: u32 = 15;
+const RAM_SIZE: usize = 0x80;
+const CHANNEL_REGISTERS: usize = 0x40;
+
+/// Expansion audio of the Namco 163: up to eight wavetable channels
+///
+/// The channel registers and waveforms share the 128 bytes of internal RAM with the game.
+/// Enabled channels are updated one after another, so their outputs are time-multiplexed.
+pub struct Namco163Audio {
+    ram: [u8; RAM_SIZE],
+    address: u8,
+    auto_increment: bool,
+    enabled: bool,
+    cycles: u32,
+    current_channel: usize,
+    outputs: [i16; 8],
+}
+impl Namco163Audio {
+    pub const fn new() -> Self {
+        Self {
+            ram: [0; RAM_SIZE],
+            address: 0,
+            auto_increment: false,
+            enabled: true,
+            cycles: 0,
+            current_channel: 7,
+            outputs: [0; 8],
+        }
+    }
+
+    /// Resets the address register and the channel sequencing,
+    /// the internal RAM keeps its contents since it can be battery backed
+    pub fn reset(&mut self) {
+        self.address = 0;
+        self.auto_increment = false;
+        self.enabled = true;
+        self.cycles = 0;
+        self.current_channel = 7;
+        self.outputs = [0; 8];
+    }
+
+    #[inline]
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    #[inline]
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    #[inline]
+    fn channel_count(&self) -> usize {
+        (((self.ram[0x7F] >> 4) & 0x07) as usize) + 1
+    }
+
+    #[inline]
+    fn step_address(&mut self) {
+        if self.auto_increment {
+            self.address = (self.address + 1) & 0x7F;
+        }
+    }
+
+    /// Reads the internal RAM at the current address ($4800)
+    pub fn read_data(&mut self) -> u8 {
+        let data = self.ram[self.address as usize];
+        self.step_address();
+        data
+    }
+
+    /// Writes the internal RAM at the current address ($4800)
+    pub fn write_data(&mut self, data: u8) {
+        self.ram[self.address as usize] = data;
+        self.step_address();
+    }
+
+    /// Sets the internal RAM address and auto increment flag ($F800)
+    pub fn write_address(&mut self, data: u8) {
+        self.address = data & 0x7F;
+        self.auto_increment = (data & 0x80) != 0;
+    }
+
+    /// Enables or disables sound output ($E000 bit 6)
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn update_channel(&mut self, channel: usize) {
+        let base = CHANNEL_REGISTERS + channel * 8;
+        let regs = &self.ram[base..(base + 8)];
+
+        let frequency =
+            (regs[0] as u32) | ((regs[2] as u32) << 8) | (((regs[4] & 0x03) as u32) << 16);
+        let mut phase = (regs[1] as u32) | ((regs[3] as u32) << 8) | ((regs[5] as u32) << 16);
+        let length = (256 - ((regs[4] & 0xFC) as u32)) << 16;
+        let wave_address = regs[6] as u32;
+        let volume = (regs[7] & 0x0F) as i16;
+
+        phase = (phase + frequency) % length;
+
+        // Samples are 4 bit wide and stored low nibble first
+        let sample_address = ((wave_address + (phase >> 16)) & 0xFF) as usize;
+        let sample_byte = self.ram[sample_address >> 1];
+        let sample = if (sample_address & 0x01) == 0 {
+            sample_byte & 0x0F
+        } else {
+            sample_byte >> 4
+        };
+        self.outputs[channel] = ((sample as i16) - 8) * volume;
+
+        self.ram[base + 1] = phase as u8;
+        self.ram[base + 3] = (phase >> 8) as u8;
+        self.ram[base + 5] = (phase >> 16) as u8;
+    }
+
+    /// Advances the channels by one CPU cycle
+    pub fn clock(&mut self) {
+        self.cycles += 1;
+        if self.cycles == CHANNEL_PERIOD {
+            self.cycles = 0;
+
+            let first_channel = 8 - self.channel_count();
+            self.update_channel(self.current_channel);
+            self.current_channel = if self.current_channel <= first_channel {
+                7
+            } else {
+                self.current_channel - 1
+            };
+        }
+    }
+
+    pub fn sample(&self) -> Sample {
+        if !self.enabled {
+            return 0.0;
+        }
+
+        // The real chip switches between the channel outputs at a high frequency,
+        // averaging them avoids the resulting aliasing at lower sample rates.
+        let count = self.channel_count();
+        let total: i16 = self.outputs[(8 - count)..].iter().sum();
+        0.0025 * (total as f32) / (count as f32)
+    }
+}
@@ -0,0 +1,260 @@
+use crate::audio::Sample;
+
+// Tone and noise counters of the 5B advance once every 16 CPU cycles, the envelope twice as often
+const TONE_PRESCALER: u8 = 16;
+const ENVELOPE_PRESCALER: u8 = 8;
+
+/// Converts a 5 bit volume level into an amplitude, every level is 1.5 dB
+fn level_to_volume(level: u8) -> f32 {
+    if level == 0 {
+        0.0
+    } else {
+        10.0f32.powf(-1.5 * ((31 - level) as f32) / 20.0)
+    }
+}
+
+struct ToneChannel {
+    period: u16,
+    counter: u16,
+    output: bool,
+    volume: u8,
+    use_envelope: bool,
+    tone_disabled: bool,
+    noise_disabled: bool,
+}
+impl ToneChannel {
+    const fn new() -> Self {
+        Self {
+            period: 0,
+            counter: 0,
+            output: false,
+            volume: 0,
+            use_envelope: false,
+            tone_disabled: true,
+            noise_disabled: true,
+        }
+    }
+
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.period {
+            self.counter = 0;
+            self.output = !self.output;
+        }
+    }
+
+    /// Returns the 5 bit volume level of the channel
+    fn level(&self, noise: bool, envelope_level: u8) -> u8 {
+        let tone_on = self.output || self.tone_disabled;
+        let noise_on = noise || self.noise_disabled;
+        if tone_on && noise_on {
+            if self.use_envelope {
+                envelope_level
+            } else if self.volume == 0 {
+                0
+            } else {
+                // Channel volume only has 4 bits and uses every second envelope level
+                (self.volume << 1) | 0x01
+            }
+        } else {
+            0
+        }
+    }
+}
+
+struct Noise {
+    period: u8,
+    counter: u8,
+    shift: u32,
+}
+impl Noise {
+    const fn new() -> Self {
+        Self {
+            period: 0,
+            counter: 0,
+            shift: 1,
+        }
+    }
+
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.period {
+            self.counter = 0;
+            // 17 bit LFSR
+            let feedback = (self.shift ^ (self.shift >> 3)) & 0x01;
+            self.shift = (self.shift >> 1) | (feedback << 16);
+        }
+    }
+
+    #[inline]
+    fn output(&self) -> bool {
+        (self.shift & 0x01) != 0
+    }
+}
+
+struct Envelope {
+    period: u16,
+    counter: u16,
+    shape: u8,
+    step: u8,
+    attack: bool,
+    holding: bool,
+    held_level: u8,
+}
+impl Envelope {
+    const fn new() -> Self {
+        Self {
+            period: 0,
+            counter: 0,
+            shape: 0,
+            step: 0,
+            attack: false,
+            holding: false,
+            held_level: 0,
+        }
+    }
+
+    fn restart(&mut self, shape: u8) {
+        self.shape = shape & 0x0F;
+        self.counter = 0;
+        self.step = 0;
+        self.attack = (self.shape & 0x04) != 0;
+        self.holding = false;
+    }
+
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter < self.period {
+            return;
+        }
+        self.counter = 0;
+
+        if self.holding {
+            return;
+        }
+
+        self.step += 1;
+        if self.step == 32 {
+            let continue_flag = (self.shape & 0x08) != 0;
+            let alternate = (self.shape & 0x02) != 0;
+            let hold = (self.shape & 0x01) != 0;
+
+            if !continue_flag {
+                self.holding = true;
+                self.held_level = 0;
+            } else if hold {
+                self.holding = true;
+                self.held_level = if self.attack != alternate { 31 } else { 0 };
+            } else {
+                if alternate {
+                    self.attack = !self.attack;
+                }
+                self.step = 0;
+            }
+        }
+    }
+
+    fn level(&self) -> u8 {
+        if self.holding {
+            self.held_level
+        } else if self.attack {
+            self.step
+        } else {
+            31 - self.step
+        }
+    }
+}
+
+/// Expansion audio of the Sunsoft 5B, a variant of the AY-3-8910:
+/// three square channels with a shared noise generator and envelope
+pub struct Sunsoft5bAudio {
+    channels: [ToneChannel; 3],
+    noise: Noise,
+    envelope: Envelope,
+    register: u8,
+    tone_prescaler: u8,
+    envelope_prescaler: u8,
+}
+impl Sunsoft5bAudio {
+    pub const fn new() -> Self {
+        Self {
+            channels: [ToneChannel::new(), ToneChannel::new(), ToneChannel::new()],
+            noise: Noise::new(),
+            envelope: Envelope::new(),
+            register: 0,
+            tone_prescaler: 0,
+            envelope_prescaler: 0,
+        }
+    }
+
+    /// Selects the register written by the next data write ($C000)
+    pub fn write_register(&mut self, data: u8) {
+        self.register = data;
+    }
+
+    /// Writes to the selected register ($E000)
+    pub fn write_data(&mut self, data: u8) {
+        // The upper nibble of the register select acts as a chip select
+        if (self.register & 0xF0) != 0 {
+            return;
+        }
+
+        match self.register {
+            0x00 | 0x02 | 0x04 => {
+                let channel = &mut self.channels[(self.register >> 1) as usize];
+                channel.period = (channel.period & 0x0F00) | (data as u16);
+            }
+            0x01 | 0x03 | 0x05 => {
+                let channel = &mut self.channels[(self.register >> 1) as usize];
+                channel.period = (channel.period & 0x00FF) | (((data & 0x0F) as u16) << 8);
+            }
+            0x06 => self.noise.period = data & 0x1F,
+            0x07 => {
+                for (i, channel) in self.channels.iter_mut().enumerate() {
+                    channel.tone_disabled = ((data >> i) & 0x01) != 0;
+                    channel.noise_disabled = ((data >> (i + 3)) & 0x01) != 0;
+                }
+            }
+            0x08..=0x0A => {
+                let channel = &mut self.channels[(self.register - 0x08) as usize];
+                channel.volume = data & 0x0F;
+                channel.use_envelope = (data & 0x10) != 0;
+            }
+            0x0B => self.envelope.period = (self.envelope.period & 0xFF00) | (data as u16),
+            0x0C => self.envelope.period = (self.envelope.period & 0x00FF) | ((data as u16) << 8),
+            0x0D => self.envelope.restart(data),
+            _ => {}
+        }
+    }
+
+    /// Advances the channels by one CPU cycle
+    pub fn clock(&mut self) {
+        self.tone_prescaler += 1;
+        if self.tone_prescaler == TONE_PRESCALER {
+            self.tone_prescaler = 0;
+            for channel in self.channels.iter_mut() {
+                channel.clock();
+            }
+            self.noise.clock();
+        }
+
+        self.envelope_prescaler += 1;
+        if self.envelope_prescaler == ENVELOPE_PRESCALER {
+            self.envelope_prescaler = 0;
+            self.envelope.clock();
+        }
+    }
+
+    pub fn sample(&self) -> Sample {
+        let noise = self.noise.output();
+        let envelope_level = self.envelope.level();
+        let total: f32 = self
+            .channels
+            .iter()
+            .map(|channel| level_to_volume(channel.level(noise, envelope_level)))
+            .sum();
+
+        // A channel at full volume is about as loud as a 2A03 pulse channel at full volume
+        0.11 * total
+    }
+}
@@ -0,0 +1,189 @@
+use crate::audio::Sample;
+
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    ignore_duty: bool,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+}
+impl Vrc6Pulse {
+    const fn new() -> Self {
+        Self {
+            volume: 0,
+            duty: 0,
+            ignore_duty: false,
+            period: 0,
+            enabled: false,
+            timer: 0,
+            step: 0,
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0 => {
+                self.ignore_duty = (data & 0x80) != 0;
+                self.duty = (data >> 4) & 0x07;
+                self.volume = data & 0x0F;
+            }
+            1 => self.period = (self.period & 0x0F00) | (data as u16),
+            2 => {
+                self.period = (self.period & 0x00FF) | (((data & 0x0F) as u16) << 8);
+                self.enabled = (data & 0x80) != 0;
+                if !self.enabled {
+                    // Disabling the channel resets the duty cycle
+                    self.step = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if self.enabled {
+            if self.timer == 0 {
+                self.timer = self.period >> shift;
+                self.step = (self.step + 1) & 0x0F;
+            } else {
+                self.timer -= 1;
+            }
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || (self.step <= self.duty)) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+struct Vrc6Saw {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+impl Vrc6Saw {
+    const fn new() -> Self {
+        Self {
+            rate: 0,
+            period: 0,
+            enabled: false,
+            timer: 0,
+            step: 0,
+            accumulator: 0,
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0 => self.rate = data & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | (data as u16),
+            2 => {
+                self.period = (self.period & 0x00FF) | (((data & 0x0F) as u16) << 8);
+                self.enabled = (data & 0x80) != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if self.enabled {
+            if self.timer == 0 {
+                self.timer = self.period >> shift;
+
+                // The accumulator is only updated on every second step and reset after 7 steps
+                self.step += 1;
+                if self.step == 14 {
+                    self.step = 0;
+                    self.accumulator = 0;
+                } else if (self.step & 0x01) == 0 {
+                    self.accumulator = self.accumulator.wrapping_add(self.rate);
+                }
+            } else {
+                self.timer -= 1;
+            }
+        }
+    }
+
+    #[inline]
+    fn output(&self) -> u8 {
+        if self.enabled {
+            self.accumulator >> 3
+        } else {
+            0
+        }
+    }
+}
+
+/// Expansion audio of the Konami VRC6: two pulse channels and a sawtooth channel
+pub struct Vrc6Audio {
+    pulse_channel_1: Vrc6Pulse,
+    pulse_channel_2: Vrc6Pulse,
+    saw_channel: Vrc6Saw,
+    halt: bool,
+    frequency_shift: u8,
+}
+impl Vrc6Audio {
+    pub const fn new() -> Self {
+        Self {
+            pulse_channel_1: Vrc6Pulse::new(),
+            pulse_channel_2: Vrc6Pulse::new(),
+            saw_channel: Vrc6Saw::new(),
+            halt: false,
+            frequency_shift: 0,
+        }
+    }
+
+    /// Writes to the audio registers
+    ///
+    /// The register is given as the upper nibble of the CPU address ($9, $A or $B)
+    /// followed by the register index (0 to 3)
+    pub fn write(&mut self, register: u8, index: u16, data: u8) {
+        match (register, index) {
+            (0x9, 3) => {
+                self.halt = (data & 0x01) != 0;
+                self.frequency_shift = if (data & 0x04) != 0 {
+                    8
+                } else if (data & 0x02) != 0 {
+                    4
+                } else {
+                    0
+                };
+            }
+            (0x9, _) => self.pulse_channel_1.write(index, data),
+            (0xA, _) => self.pulse_channel_2.write(index, data),
+            (0xB, _) => self.saw_channel.write(index, data),
+            _ => {}
+        }
+    }
+
+    /// Advances the channels by one CPU cycle
+    pub fn clock(&mut self) {
+        if !self.halt {
+            self.pulse_channel_1.clock(self.frequency_shift);
+            self.pulse_channel_2.clock(self.frequency_shift);
+            self.saw_channel.clock(self.frequency_shift);
+        }
+    }
+
+    pub fn sample(&self) -> Sample {
+        let output = self.pulse_channel_1.output()
+            + self.pulse_channel_2.output()
+            + self.saw_channel.output();
+
+        // One volume step is roughly as loud as one step of a 2A03 pulse channel
+        0.00752 * (output as f32)
+    }
+}
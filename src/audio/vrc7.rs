@@ -0,0 +1,491 @@
+use crate::audio::Sample;
+use std::f64::consts::PI;
+
+// The synthesizer produces one sample every 36 CPU cycles (3.58 MHz / 72)
+const SAMPLE_PERIOD: u32 = 36;
+const CHANNEL_COUNT: usize = 6;
+
+// Built-in instruments of the VRC7, instrument 0 is the user defined one
+const PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+];
+
+// Frequency multipliers times two
+const MULTIPLIERS: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+// Key scale level attenuation per octave, in 0.375 dB steps
+const KSL_TABLE: [i32; 16] = [
+    0, 24, 32, 37, 40, 43, 45, 47, 48, 50, 51, 52, 53, 54, 55, 56,
+];
+
+// Envelope increments for the four fractional rate steps
+const EG_PATTERNS: [[u32; 8]; 4] = [
+    [0, 1, 0, 1, 0, 1, 0, 1],
+    [0, 1, 0, 1, 1, 1, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 1],
+];
+
+const VIBRATO_TABLE: [i32; 8] = [0, 1, 2, 1, 0, -1, -2, -1];
+const VIBRATO_PERIOD: u32 = 1024; // Samples per vibrato step, about 6.1 Hz
+const TREMOLO_PERIOD: u32 = 512; // Samples per tremolo step, about 3.7 Hz
+const TREMOLO_DEPTH: u32 = 13; // 4.8 dB in 0.375 dB steps
+
+// Attenuation is measured in steps of 0.375 dB
+const MAX_ATTENUATION: u32 = 127;
+const PHASE_MASK: u32 = 0x7FFFF;
+
+#[derive(Clone, Copy)]
+struct OperatorPatch {
+    tremolo: bool,
+    vibrato: bool,
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: u8,
+    key_scale_level: u8,
+    rectify: bool,
+    attack_rate: u8,
+    decay_rate: u8,
+    sustain_level: u8,
+    release_rate: u8,
+}
+impl OperatorPatch {
+    fn from_bytes(patch: &[u8; 8], carrier: bool) -> Self {
+        let index = carrier as usize;
+        let flags = patch[index];
+        Self {
+            tremolo: (flags & 0x80) != 0,
+            vibrato: (flags & 0x40) != 0,
+            sustained: (flags & 0x20) != 0,
+            key_scale_rate: (flags & 0x10) != 0,
+            multiplier: flags & 0x0F,
+            key_scale_level: patch[2 + index] >> 6,
+            rectify: (patch[3] & (if carrier { 0x10 } else { 0x08 })) != 0,
+            attack_rate: patch[4 + index] >> 4,
+            decay_rate: patch[4 + index] & 0x0F,
+            sustain_level: patch[6 + index] >> 4,
+            release_rate: patch[6 + index] & 0x0F,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+struct Operator {
+    phase: u32,
+    state: EnvelopeState,
+    attenuation: u32,
+    output: i32,
+    previous_output: i32,
+}
+impl Operator {
+    const fn new() -> Self {
+        Self {
+            phase: 0,
+            state: EnvelopeState::Release,
+            attenuation: MAX_ATTENUATION,
+            output: 0,
+            previous_output: 0,
+        }
+    }
+
+    fn key_on(&mut self) {
+        self.phase = 0;
+        self.state = EnvelopeState::Attack;
+    }
+
+    fn key_off(&mut self) {
+        self.state = EnvelopeState::Release;
+    }
+
+    fn update_phase(&mut self, patch: &OperatorPatch, fnum: u16, block: u8, vibrato_step: usize) {
+        let mut fnum = fnum as i32;
+        if patch.vibrato {
+            fnum += ((fnum >> 6) * VIBRATO_TABLE[vibrato_step]) >> 1;
+        }
+
+        let increment = (((fnum as u32) * MULTIPLIERS[patch.multiplier as usize]) << block) >> 1;
+        self.phase = (self.phase + increment) & PHASE_MASK;
+    }
+
+    fn update_envelope(&mut self, patch: &OperatorPatch, key_scale: u8, release: u8, counter: u32) {
+        let rate = match self.state {
+            EnvelopeState::Attack => patch.attack_rate,
+            EnvelopeState::Decay => patch.decay_rate,
+            EnvelopeState::Sustain => {
+                if patch.sustained {
+                    0
+                } else {
+                    patch.release_rate
+                }
+            }
+            EnvelopeState::Release => release,
+        };
+        let increment = envelope_increment(rate, key_scale, patch.key_scale_rate, counter);
+
+        match self.state {
+            EnvelopeState::Attack => {
+                if patch.attack_rate == 15 {
+                    self.attenuation = 0;
+                } else if increment > 0 {
+                    // The attack curve is exponential
+                    let decrement = ((self.attenuation * increment) >> 3) + 1;
+                    self.attenuation = self.attenuation.saturating_sub(decrement);
+                }
+
+                if self.attenuation == 0 {
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+            EnvelopeState::Decay => {
+                self.attenuation = (self.attenuation + increment).min(MAX_ATTENUATION);
+                if self.attenuation >= ((patch.sustain_level as u32) << 3) {
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+            EnvelopeState::Sustain | EnvelopeState::Release => {
+                self.attenuation = (self.attenuation + increment).min(MAX_ATTENUATION);
+            }
+        }
+    }
+
+    fn update_output(
+        &mut self,
+        tables: &Tables,
+        phase_offset: i32,
+        attenuation: u32,
+        rectify: bool,
+    ) {
+        let phase = (((self.phase >> 9) as i32) + phase_offset) as u32 & 0x3FF;
+        self.previous_output = self.output;
+        self.output = tables.wave(phase, self.attenuation + attenuation, rectify);
+    }
+}
+
+fn envelope_increment(rate: u8, key_scale: u8, key_scale_rate: bool, counter: u32) -> u32 {
+    if rate == 0 {
+        return 0;
+    }
+
+    let key_scale = if key_scale_rate {
+        key_scale
+    } else {
+        key_scale >> 2
+    };
+    let rate = ((rate << 2) + key_scale).min(63);
+    let rate_hi = (rate >> 2) as u32;
+    let pattern = &EG_PATTERNS[(rate & 0x03) as usize];
+
+    if rate_hi < 13 {
+        let shift = 13 - rate_hi;
+        if (counter & ((1 << shift) - 1)) == 0 {
+            pattern[((counter >> shift) & 0x07) as usize]
+        } else {
+            0
+        }
+    } else {
+        (pattern[(counter & 0x07) as usize] + 1) << (rate_hi - 13)
+    }
+}
+
+/// Log-sine and exponent lookup tables, like they are stored in the ROM of the chip
+struct Tables {
+    log_sin: [u32; 256],
+    exp: [u32; 256],
+}
+impl Tables {
+    fn new() -> Self {
+        let mut log_sin = [0; 256];
+        let mut exp = [0; 256];
+        for (i, (log_sin, exp)) in log_sin.iter_mut().zip(exp.iter_mut()).enumerate() {
+            let sin = (((i as f64) + 0.5) * PI / 512.0).sin();
+            *log_sin = (-sin.log2() * 256.0).round() as u32;
+            *exp = ((2.0f64).powf(-(i as f64) / 256.0) * 4095.0).round() as u32;
+        }
+        Self { log_sin, exp }
+    }
+
+    /// Computes one output value of a sine wave with the given attenuation
+    fn wave(&self, phase: u32, attenuation: u32, rectify: bool) -> i32 {
+        let negative = (phase & 0x200) != 0;
+        if negative && rectify {
+            return 0;
+        }
+
+        let index = if (phase & 0x100) != 0 {
+            0xFF - (phase & 0xFF)
+        } else {
+            phase & 0xFF
+        };
+
+        // One attenuation step is 1/16 of a factor of two in the log domain
+        let level = self.log_sin[index as usize] + (attenuation << 4);
+        let shift = level >> 8;
+        let value = if shift < 12 {
+            (self.exp[(level & 0xFF) as usize] >> shift) as i32
+        } else {
+            0
+        };
+
+        if negative {
+            -value
+        } else {
+            value
+        }
+    }
+}
+
+struct Channel {
+    fnum: u16,
+    block: u8,
+    key_on: bool,
+    sustain: bool,
+    instrument: u8,
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+}
+impl Channel {
+    const fn new() -> Self {
+        Self {
+            fnum: 0,
+            block: 0,
+            key_on: false,
+            sustain: false,
+            instrument: 0,
+            volume: 0,
+            modulator: Operator::new(),
+            carrier: Operator::new(),
+        }
+    }
+
+    fn set_key_on(&mut self, key_on: bool) {
+        if key_on && !self.key_on {
+            self.modulator.key_on();
+            self.carrier.key_on();
+        } else if !key_on && self.key_on {
+            self.modulator.key_off();
+            self.carrier.key_off();
+        }
+        self.key_on = key_on;
+    }
+
+    #[inline]
+    fn key_scale(&self) -> u8 {
+        (self.block << 1) | ((self.fnum >> 8) as u8)
+    }
+
+    fn key_scale_level(&self, patch: &OperatorPatch) -> u32 {
+        if patch.key_scale_level == 0 {
+            0
+        } else {
+            let level =
+                (KSL_TABLE[(self.fnum >> 5) as usize] - 8 * (7 - (self.block as i32))).max(0);
+            ((level << 1) >> (3 - patch.key_scale_level)) as u32
+        }
+    }
+
+    fn release_rate(&self, patch: &OperatorPatch) -> u8 {
+        if self.sustain {
+            5
+        } else if patch.sustained {
+            patch.release_rate
+        } else {
+            7
+        }
+    }
+}
+
+/// Expansion audio of the Konami VRC7, a six channel FM synthesizer derived from the YM2413 (OPLL)
+///
+/// Only the melodic mode of the OPLL exists on the VRC7, the rhythm section is missing.
+pub struct Vrc7Audio {
+    tables: Tables,
+    custom_patch: [u8; 8],
+    channels: [Channel; CHANNEL_COUNT],
+    register: u8,
+    cycles: u32,
+    envelope_counter: u32,
+    vibrato_counter: u32,
+    tremolo_counter: u32,
+    output: i32,
+}
+impl Vrc7Audio {
+    pub fn new() -> Self {
+        Self {
+            tables: Tables::new(),
+            custom_patch: [0; 8],
+            channels: [
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+            ],
+            register: 0,
+            cycles: 0,
+            envelope_counter: 0,
+            vibrato_counter: 0,
+            tremolo_counter: 0,
+            output: 0,
+        }
+    }
+
+    /// Clears all registers and channels, the lookup tables are kept since they never change
+    pub fn reset(&mut self) {
+        self.custom_patch = [0; 8];
+        for channel in self.channels.iter_mut() {
+            *channel = Channel::new();
+        }
+        self.register = 0;
+        self.cycles = 0;
+        self.envelope_counter = 0;
+        self.vibrato_counter = 0;
+        self.tremolo_counter = 0;
+        self.output = 0;
+    }
+
+    /// Selects the register written by the next data write ($9010)
+    pub fn write_register(&mut self, data: u8) {
+        self.register = data;
+    }
+
+    /// Writes to the selected register ($9030)
+    pub fn write_data(&mut self, data: u8) {
+        let index = (self.register & 0x0F) as usize;
+        match self.register & 0xF0 {
+            0x00 if index < 8 => self.custom_patch[index] = data,
+            0x10 if index < CHANNEL_COUNT => {
+                let channel = &mut self.channels[index];
+                channel.fnum = (channel.fnum & 0x100) | (data as u16);
+            }
+            0x20 if index < CHANNEL_COUNT => {
+                let channel = &mut self.channels[index];
+                channel.fnum = (channel.fnum & 0x0FF) | (((data & 0x01) as u16) << 8);
+                channel.block = (data >> 1) & 0x07;
+                channel.sustain = (data & 0x20) != 0;
+                channel.set_key_on((data & 0x10) != 0);
+            }
+            0x30 if index < CHANNEL_COUNT => {
+                let channel = &mut self.channels[index];
+                channel.instrument = data >> 4;
+                channel.volume = data & 0x0F;
+            }
+            _ => {}
+        }
+    }
+
+    fn tremolo(&self) -> u32 {
+        // Triangle wave going from 0 to the maximum depth and back
+        let step = (self.tremolo_counter / TREMOLO_PERIOD) % (TREMOLO_DEPTH * 2);
+        if step < TREMOLO_DEPTH {
+            step
+        } else {
+            TREMOLO_DEPTH * 2 - 1 - step
+        }
+    }
+
+    fn generate_sample(&mut self) {
+        self.envelope_counter = self.envelope_counter.wrapping_add(1);
+        self.vibrato_counter = (self.vibrato_counter + 1) % (VIBRATO_PERIOD * 8);
+        self.tremolo_counter = (self.tremolo_counter + 1) % (TREMOLO_PERIOD * TREMOLO_DEPTH * 2);
+
+        let vibrato_step = (self.vibrato_counter / VIBRATO_PERIOD) as usize;
+        let tremolo = self.tremolo();
+        let counter = self.envelope_counter;
+
+        let mut output = 0;
+        for channel in self.channels.iter_mut() {
+            let patch = if channel.instrument == 0 {
+                self.custom_patch
+            } else {
+                PATCHES[(channel.instrument - 1) as usize]
+            };
+            let modulator_patch = OperatorPatch::from_bytes(&patch, false);
+            let carrier_patch = OperatorPatch::from_bytes(&patch, true);
+            let total_level = ((patch[2] & 0x3F) as u32) << 1;
+            let feedback = patch[3] & 0x07;
+
+            let key_scale = channel.key_scale();
+            let (fnum, block) = (channel.fnum, channel.block);
+
+            // Modulator
+            let modulator_release = channel.release_rate(&modulator_patch);
+            let mut modulator_attenuation = total_level + channel.key_scale_level(&modulator_patch);
+            if modulator_patch.tremolo {
+                modulator_attenuation += tremolo;
+            }
+            let modulator = &mut channel.modulator;
+            modulator.update_phase(&modulator_patch, fnum, block, vibrato_step);
+            modulator.update_envelope(&modulator_patch, key_scale, modulator_release, counter);
+            let feedback_offset = if feedback == 0 {
+                0
+            } else {
+                (modulator.output + modulator.previous_output) >> (9 - feedback)
+            };
+            modulator.update_output(
+                &self.tables,
+                feedback_offset,
+                modulator_attenuation,
+                modulator_patch.rectify,
+            );
+            let modulation = modulator.output;
+
+            // Carrier
+            let carrier_release = channel.release_rate(&carrier_patch);
+            let mut carrier_attenuation =
+                ((channel.volume as u32) << 3) + channel.key_scale_level(&carrier_patch);
+            if carrier_patch.tremolo {
+                carrier_attenuation += tremolo;
+            }
+            let carrier = &mut channel.carrier;
+            carrier.update_phase(&carrier_patch, fnum, block, vibrato_step);
+            carrier.update_envelope(&carrier_patch, key_scale, carrier_release, counter);
+            carrier.update_output(
+                &self.tables,
+                modulation,
+                carrier_attenuation,
+                carrier_patch.rectify,
+            );
+
+            output += carrier.output;
+        }
+
+        self.output = output;
+    }
+
+    /// Advances the synthesizer by one CPU cycle
+    pub fn clock(&mut self) {
+        self.cycles += 1;
+        if self.cycles == SAMPLE_PERIOD {
+            self.cycles = 0;
+            self.generate_sample();
+        }
+    }
+
+    pub fn sample(&self) -> Sample {
+        // A channel at full volume is about as loud as a 2A03 pulse channel at full volume
+        0.11 * (self.output as f32) / 4095.0
+    }
+}
@@ -1,5 +1,6 @@
 use crate::types::HardwareInteger;
 use crate::*;
+use std::cell::Cell;
 use std::collections::HashMap;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -154,6 +155,7 @@ where
 {
     components: HashMap<BusHandle, BusRef<'a, TAddress, TWord>>,
     next_handle: BusHandle,
+    last_read: Cell<Option<TAddress>>,
 }
 impl<'a, TAddress, TWord> Bus<'a, TAddress, TWord>
 where
@@ -165,6 +167,7 @@ where
         Self {
             components: HashMap::new(),
             next_handle: 0,
+            last_read: Cell::new(None),
         }
     }
 
@@ -186,7 +189,14 @@ where
         self.components.remove(&handle)
     }
 
+    /// The address of the most recent access if it was a read, needed to emulate halted CPUs repeating their read
+    #[inline]
+    pub fn last_read_address(&self) -> Option<TAddress> {
+        self.last_read.get()
+    }
+
     pub fn read(&self, address: TAddress) -> TWord {
+        self.last_read.set(Some(address));
         let mut result = TWord::zero();
 
         for (_, component_ref) in self.components.iter() {
@@ -203,6 +213,7 @@ where
     }
 
     pub fn write(&self, address: TAddress, data: TWord) {
+        self.last_read.set(None);
         for (_, component_ref) in self.components.iter() {
             if let Ok(mut component) = component_ref.try_borrow_mut() {
                 if let Some(range) = component.write_range() {
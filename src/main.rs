@@ -6,28 +6,34 @@ extern crate bitflags;
 
 use audio::SampleBuffer;
 use ggez::conf::{NumSamples, WindowMode, WindowSetup};
-use ggez::event::{EventHandler, KeyCode};
+use ggez::event::{EventHandler, KeyCode, MouseButton};
 use ggez::graphics::{DrawParam, FilterMode, Font, Image, WrapMode, PxScale};
 #[allow(unused_imports)]
 use ggez::graphics::{Text, TextFragment};
 use ggez::{event, graphics, timer, Context, ContextBuilder, GameResult};
+use memory::RamInit;
 use scaler::Scaler;
 use std::cell::RefCell;
 use std::collections::VecDeque;
 use std::error::Error;
 use std::fmt::Display;
 use std::num::Wrapping;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
+use system::cheats::{cheat_path, CheatError, CheatList};
+use system::input::*;
 use system::nes::*;
+use system::ramsearch::{RamSearch, SearchFilter, ValueType};
 use util::pixels_to_data;
 use video::Color;
 
+pub mod archive;
 pub mod audio;
 pub mod bus;
 pub mod cpu;
 pub mod memory;
+pub mod patch;
 pub mod scaler;
 pub mod system;
 pub mod types;
@@ -81,7 +87,6 @@ impl Display for ArgError {
 }
 impl Error for ArgError {}
 
-pub const FRAME_RATE: u32 = 60;
 pub const SAMPLE_RATE: u32 = 44100;
 pub const SECONDS_PER_SAMPLE: f32 = 1.0 / (SAMPLE_RATE as f32);
 
@@ -146,20 +151,47 @@ fn main() -> Result<(), Box<dyn Error>> {
         Err(Box::new(ArgError))
     } else {
         let path = PathBuf::from(&args[1]);
-        run_emu(path, SCREEN_SCALE, ASPECT_RATIO, SCALER, FILTER)?;
+        // Optional second argument selects how memory is initialized on power on
+        let ram_init = match args.get(2) {
+            Some(arg) => arg.parse()?,
+            None => RamInit::default(),
+        };
+        let cartridge = load_cartridge(&path)?;
+        // Cheats are optional, a missing cheat file is not an error
+        let cheats = match CheatList::load(cheat_path(&path)) {
+            Ok(cheats) => cheats,
+            Err(CheatError::Io(_)) => CheatList::new(),
+            Err(err) => {
+                eprintln!("Failed to load cheats: {}", err);
+                CheatList::new()
+            }
+        };
+        run_emu(
+            cartridge,
+            cheats,
+            ram_init,
+            SCREEN_SCALE,
+            ASPECT_RATIO,
+            SCALER,
+            FILTER,
+        )?;
 
         Ok(())
     }
 }
 
-fn run_emu<P: AsRef<Path>>(
-    cartridge_file: P,
+fn run_emu(
+    cartridge: EmuRef<Cartridge>,
+    cheats: CheatList,
+    ram_init: RamInit,
     scale: f32,
     aspect_ratio: AspectRatio,
     scaler: Scaler,
     filter: FilterMode,
 ) -> Result<(), Box<dyn Error>> {
-    let emu = Nes::new();
+    let mut emu = Nes::new();
+    emu.set_ram_init(ram_init);
+    emu.set_cheats(cheats);
 
     let window_setup = WindowSetup::default()
         .title(&format!("{} v{}", TITLE, VERSION))
@@ -198,7 +230,7 @@ fn run_emu<P: AsRef<Path>>(
         filter,
         font,
         audio_buffer,
-        cartridge_file,
+        cartridge,
     );
 
     event::run(ctx, event_loop, state)
@@ -209,17 +241,20 @@ struct EmuState<'a> {
     scale: [f32; 2],
     scaler: Scaler,
     filter: FilterMode,
-    #[allow(dead_code)]
     cartridge: Rc<RefCell<Cartridge>>,
     controller_0: Buttons,
     controller_1: Buttons,
+    pads: [EmuRef<StandardController>; 2],
+    zapper: EmuRef<Zapper<'a>>,
+    zapper_connected: bool,
+    ram_search: Option<RamSearch>,
     scaler_output_buffer: Option<Box<[Color]>>,
     font: Font,
     audio_buffer: Arc<Mutex<SampleBuffer>>,
     run: bool,
 }
 impl<'a> EmuState<'a> {
-    pub fn new<P: AsRef<Path>>(
+    pub fn new(
         mut emu: Nes<'a>,
         scale: f32,
         aspect_ratio: AspectRatio,
@@ -227,12 +262,18 @@ impl<'a> EmuState<'a> {
         filter: FilterMode,
         font: Font,
         audio_buffer: Arc<Mutex<SampleBuffer>>,
-        cartridge_file: P,
+        cartridge: EmuRef<Cartridge>,
     ) -> Self {
-        let cartridge = load_cartridge(cartridge_file).expect("Invalid cartridge file");
-
         emu.set_cartridge(clone_ref(&cartridge));
-        emu.reset();
+        emu.power_cycle();
+
+        let pads = [
+            make_ref(StandardController::new()),
+            make_ref(StandardController::new()),
+        ];
+        emu.connect(Port::One, clone_ref(&pads[0]));
+        emu.connect(Port::Two, clone_ref(&pads[1]));
+        let zapper = make_ref(Zapper::new(emu.light_source()));
 
         Self {
             emu,
@@ -242,29 +283,48 @@ impl<'a> EmuState<'a> {
             cartridge,
             controller_0: Buttons::empty(),
             controller_1: Buttons::empty(),
+            pads,
+            zapper,
+            zapper_connected: false,
+            ram_search: None,
             scaler_output_buffer: None,
             font,
             audio_buffer,
             run: true,
         }
     }
+
+    /// Narrows down a running RAM search with the current memory contents
+    fn filter_ram_search(&mut self, filter: SearchFilter) {
+        if let Some(search) = &mut self.ram_search {
+            search.filter(self.emu.memory_snapshot(), filter);
+        }
+    }
 }
 impl<'a> EventHandler for EmuState<'a> {
     fn update(&mut self, ctx: &mut Context) -> GameResult {
-        self.emu
-            .update_input_state(self.controller_0, self.controller_1);
+        self.pads[0].borrow_mut().set_buttons(self.controller_0);
+        self.pads[1].borrow_mut().set_buttons(self.controller_1);
 
-        while timer::check_update_time(ctx, FRAME_RATE) {
+        while timer::check_update_time(ctx, self.emu.region().frame_rate()) {
             if self.run {
                 let mut locked_buffer = self.audio_buffer.lock().unwrap();
                 self.emu.next_frame(&mut locked_buffer);
             }
         }
 
-        graphics::set_window_title(
-            ctx,
-            &format!("{} v{} - {:.1} fps", TITLE, VERSION, timer::fps(ctx)),
-        );
+        let title = if let Some(name) = self.cartridge.borrow().name() {
+            format!(
+                "{} v{} - {} - {:.1} fps",
+                TITLE,
+                VERSION,
+                name,
+                timer::fps(ctx)
+            )
+        } else {
+            format!("{} v{} - {:.1} fps", TITLE, VERSION, timer::fps(ctx))
+        };
+        graphics::set_window_title(ctx, &title);
 
         timer::yield_now();
         Ok(())
@@ -324,7 +384,17 @@ impl<'a> EventHandler for EmuState<'a> {
             const TEXT_BACK_COLOR: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 1.0);
             const TEXT_FRONT_COLOR: graphics::Color = graphics::Color::new(0.5, 1.0, 0.0, 1.0);
 
-            let emu_info = format!("{}", self.emu);
+            let mut emu_info = format!("{}", self.emu);
+            if let Some(search) = &self.ram_search {
+                const SHOWN_CANDIDATES: usize = 8;
+
+                let candidates = search.candidates();
+                emu_info.push_str(&format!("\nRAM search: {} candidates\n", candidates.len()));
+                for &address in candidates.iter().take(SHOWN_CANDIDATES) {
+                    let value = search.value(address).unwrap_or(0);
+                    emu_info.push_str(&format!("{:0>4X}  {}\n", address, value));
+                }
+            }
             let emu_info_frag = TextFragment::new(emu_info)
                 .font(self.font)
                 .scale(TEXT_SCALE);
@@ -350,6 +420,14 @@ impl<'a> EventHandler for EmuState<'a> {
         Ok(())
     }
 
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        if let Err(err) = self.cartridge.borrow().save() {
+            eprintln!("Failed to write save file: {}", err);
+        }
+
+        false
+    }
+
     // Input handling currently only supports one virtual controller
 
     fn key_down_event(
@@ -370,6 +448,45 @@ impl<'a> EventHandler for EmuState<'a> {
             KeyCode::E => self.controller_0.insert(Buttons::B),
             KeyCode::R => self.controller_0.insert(Buttons::A),
             KeyCode::Space => self.run = !self.run,
+            KeyCode::F5 => self.emu.reset(),
+            KeyCode::F6 => self.emu.power_cycle(),
+            KeyCode::F8 => {
+                // Swaps the second controller for the Zapper and back
+                if self.zapper_connected {
+                    self.emu.connect(Port::Two, clone_ref(&self.pads[1]));
+                } else {
+                    self.emu.connect(Port::Two, clone_ref(&self.zapper));
+                }
+                self.zapper_connected = !self.zapper_connected;
+            }
+            KeyCode::F7 => {
+                // Toggles all cheats at once
+                let mut cheats = self.emu.cheats();
+                let enabled = !cheats.iter().any(|cheat| cheat.enabled());
+                for cheat in cheats.iter_mut() {
+                    cheat.set_enabled(enabled);
+                }
+            }
+            KeyCode::F9 => {
+                // Starts a new RAM search, narrowed down with F10-F12 and Page Up/Down
+                let snapshot = self.emu.memory_snapshot();
+                self.ram_search = Some(RamSearch::new(snapshot, ValueType::U8));
+            }
+            KeyCode::F10 => self.filter_ram_search(SearchFilter::Unchanged),
+            KeyCode::F11 => self.filter_ram_search(SearchFilter::Changed),
+            KeyCode::PageUp => self.filter_ram_search(SearchFilter::Increased),
+            KeyCode::PageDown => self.filter_ram_search(SearchFilter::Decreased),
+            KeyCode::F12 => {
+                // Freezes the value once the search is down to a single address
+                if let Some(search) = &self.ram_search {
+                    if let [address] = *search.candidates() {
+                        let mut cheats = self.emu.cheats();
+                        for cheat in search.freeze(address, "RAM search") {
+                            cheats.add(cheat);
+                        }
+                    }
+                }
+            }
             KeyCode::S => {
                 if !self.run {
                     let mut locked_buffer = self.audio_buffer.lock().unwrap();
@@ -437,4 +554,43 @@ impl<'a> EventHandler for EmuState<'a> {
             _ => {}
         }
     }
+
+    fn mouse_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: MouseButton,
+        _x: f32,
+        _y: f32,
+    ) {
+        if button == MouseButton::Left {
+            self.zapper.borrow_mut().set_trigger(true);
+        }
+    }
+
+    fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, _x: f32, _y: f32) {
+        if button == MouseButton::Left {
+            self.zapper.borrow_mut().set_trigger(false);
+        }
+    }
+
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        // Converts window coordinates to NES pixels
+        let factor = self.scaler.scale_factor() as f32;
+        let pixel_x = (x / (self.scale[0] * factor)) as isize;
+        let pixel_y = (y / (self.scale[1] * factor)) as isize;
+
+        let screen = self.emu.screen();
+        let on_screen = (x >= 0.0)
+            && (y >= 0.0)
+            && (pixel_x < screen.width() as isize)
+            && (pixel_y < screen.height() as isize);
+        std::mem::drop(screen);
+
+        let position = if on_screen {
+            Some((pixel_x, pixel_y))
+        } else {
+            None
+        };
+        self.zapper.borrow_mut().set_position(position);
+    }
 }
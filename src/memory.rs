@@ -1,7 +1,92 @@
 use crate::bus::*;
 use crate::types::HardwareInteger;
 use crate::*;
+use std::error::Error;
+use std::fmt::Display;
 use std::marker::PhantomData;
+use std::str::FromStr;
+
+/// Contents of memory after power on, real hardware starts out in a mostly random state
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RamInit {
+    Zeros,
+    /// All bytes set to $FF
+    Ones,
+    /// Alternating blocks of four $00 and four $FF bytes, like FCEUX
+    Fceux,
+    /// Pseudo random bytes, the same seed always produces the same contents
+    Random(u64),
+}
+impl RamInit {
+    /// Returns the initial value of the byte at `index`,
+    /// `salt` makes the random pattern differ between memories
+    pub fn value(self, index: usize, salt: u64) -> u8 {
+        match self {
+            RamInit::Zeros => 0x00,
+            RamInit::Ones => 0xFF,
+            RamInit::Fceux => {
+                if (index & 0x04) != 0 {
+                    0xFF
+                } else {
+                    0x00
+                }
+            }
+            RamInit::Random(seed) => {
+                // SplitMix64
+                let mut z = seed
+                    .wrapping_add(salt.wrapping_mul(0xD1B54A32D192ED03))
+                    .wrapping_add((index as u64).wrapping_mul(0x9E3779B97F4A7C15));
+                z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+                (z ^ (z >> 31)) as u8
+            }
+        }
+    }
+
+    pub fn fill<TWord: HardwareInteger>(self, data: &mut [TWord], salt: u64) {
+        for (index, word) in data.iter_mut().enumerate() {
+            *word = TWord::from_u8(self.value(index, salt)).unwrap();
+        }
+    }
+}
+impl Default for RamInit {
+    fn default() -> Self {
+        RamInit::Zeros
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseRamInitError;
+impl Display for ParseRamInitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Unknown RAM init pattern, expected zeros, ones, fceux or random[:seed]")
+    }
+}
+impl Error for ParseRamInitError {}
+
+impl FromStr for RamInit {
+    type Err = ParseRamInitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "zeros" => Ok(RamInit::Zeros),
+            "ones" | "ff" => Ok(RamInit::Ones),
+            "fceux" => Ok(RamInit::Fceux),
+            "random" => {
+                // Without a seed every run gets different contents
+                let seed = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |time| time.as_nanos() as u64);
+                Ok(RamInit::Random(seed))
+            }
+            other => other
+                .strip_prefix("random:")
+                .and_then(|seed| seed.parse().ok())
+                .map(RamInit::Random)
+                .ok_or(ParseRamInitError),
+        }
+    }
+}
 
 pub struct Ram<TAddress, TWord>
 where
@@ -29,6 +114,16 @@ where
     pub fn create(size: TAddress, start_address: TAddress) -> EmuRef<Self> {
         make_ref(Self::new(size, start_address))
     }
+
+    #[inline]
+    pub fn data(&self) -> &[TWord] {
+        &self.data
+    }
+
+    #[inline]
+    pub fn fill(&mut self, pattern: RamInit, salt: u64) {
+        pattern.fill(&mut self.data, salt);
+    }
 }
 impl<TAddress, TWord> BusComponent<TAddress, TWord> for Ram<TAddress, TWord>
 where
@@ -54,3 +149,215 @@ where
         self.data[address.to_usize().unwrap()] = data;
     }
 }
+
+/// Serial EEPROM chips of the 24Cxx family
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum EepromKind {
+    /// 128 bytes, the first byte of a transfer contains the word address and direction
+    X24C01,
+    /// 256 bytes, transfers start with a device address followed by the word address
+    X24C02,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum EepromPhase {
+    Standby,
+    Device,
+    Address,
+    Write,
+    Read,
+}
+
+/// An I2C EEPROM emulated on the level of its two signal lines
+pub struct Eeprom {
+    kind: EepromKind,
+    data: Vec<u8>,
+    scl: bool,
+    sda: bool,
+    output: bool,
+    phase: EepromPhase,
+    bit: u8,
+    shift: u8,
+    address: u8,
+    read_mode: bool,
+    master_ack: bool,
+    // The clock falls once after a start condition before the first bit is sent
+    start_pending: bool,
+}
+impl Eeprom {
+    pub fn new(kind: EepromKind) -> Self {
+        let size = match kind {
+            EepromKind::X24C01 => 128,
+            EepromKind::X24C02 => 256,
+        };
+
+        Self {
+            kind,
+            data: vec![0xFF; size],
+            scl: false,
+            sda: false,
+            output: true,
+            phase: EepromPhase::Standby,
+            bit: 0,
+            shift: 0,
+            address: 0,
+            read_mode: false,
+            master_ack: false,
+            start_pending: false,
+        }
+    }
+
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn load_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.data.len());
+        self.data[..len].copy_from_slice(&data[..len]);
+    }
+
+    /// State of the data line driven by the EEPROM, high when released
+    #[inline]
+    pub const fn output(&self) -> bool {
+        self.output
+    }
+
+    #[inline]
+    fn address_mask(&self) -> u8 {
+        (self.data.len() - 1) as u8
+    }
+
+    #[inline]
+    fn page_mask(&self) -> u8 {
+        match self.kind {
+            EepromKind::X24C01 => 0x03,
+            EepromKind::X24C02 => 0x07,
+        }
+    }
+
+    /// Sets the clock and data lines driven by the host
+    pub fn write(&mut self, scl: bool, sda: bool) {
+        if self.scl && scl && (self.sda != sda) {
+            if !sda {
+                // Start condition, also used for a repeated start
+                self.phase = match self.kind {
+                    EepromKind::X24C01 => EepromPhase::Address,
+                    EepromKind::X24C02 => EepromPhase::Device,
+                };
+                self.bit = 0;
+                self.shift = 0;
+                self.start_pending = true;
+            } else {
+                // Stop condition
+                self.phase = EepromPhase::Standby;
+            }
+            self.output = true;
+        } else if !self.scl && scl {
+            self.clock_rising(sda);
+        } else if self.scl && !scl {
+            self.clock_falling();
+        }
+
+        self.scl = scl;
+        self.sda = sda;
+    }
+
+    fn clock_rising(&mut self, sda: bool) {
+        match self.phase {
+            EepromPhase::Standby => {}
+            EepromPhase::Read => {
+                if self.bit == 8 {
+                    self.master_ack = !sda;
+                }
+            }
+            _ => {
+                if self.bit < 8 {
+                    self.shift = (self.shift << 1) | (sda as u8);
+                }
+            }
+        }
+    }
+
+    fn clock_falling(&mut self) {
+        if self.phase == EepromPhase::Standby {
+            return;
+        }
+        if self.start_pending {
+            self.start_pending = false;
+            return;
+        }
+
+        if self.bit < 8 {
+            self.bit += 1;
+            if self.phase == EepromPhase::Read {
+                // Data is shifted out MSB first, the line is released for the acknowledge
+                self.output = if self.bit < 8 {
+                    ((self.shift >> (7 - self.bit)) & 0x01) != 0
+                } else {
+                    true
+                };
+            } else if self.bit == 8 {
+                self.receive_byte();
+            }
+        } else {
+            // Acknowledge cycle done
+            self.bit = 0;
+            self.output = true;
+            self.phase = match self.phase {
+                EepromPhase::Device if self.read_mode => EepromPhase::Read,
+                EepromPhase::Device => EepromPhase::Address,
+                EepromPhase::Address if self.read_mode => EepromPhase::Read,
+                EepromPhase::Address => EepromPhase::Write,
+                EepromPhase::Read if self.master_ack => {
+                    self.address = self.address.wrapping_add(1) & self.address_mask();
+                    EepromPhase::Read
+                }
+                EepromPhase::Read => EepromPhase::Standby,
+                phase => phase,
+            };
+
+            if self.phase == EepromPhase::Read {
+                self.shift = self.data[self.address as usize];
+                self.output = (self.shift & 0x80) != 0;
+            } else {
+                self.shift = 0;
+            }
+        }
+    }
+
+    fn receive_byte(&mut self) {
+        let byte = self.shift;
+        match (self.phase, self.kind) {
+            (EepromPhase::Device, _) => {
+                if (byte & 0xF0) != 0xA0 {
+                    // Addressed to a different device
+                    self.phase = EepromPhase::Standby;
+                    self.output = true;
+                    return;
+                }
+                self.read_mode = (byte & 0x01) != 0;
+            }
+            (EepromPhase::Address, EepromKind::X24C01) => {
+                self.address = byte >> 1;
+                self.read_mode = (byte & 0x01) != 0;
+            }
+            (EepromPhase::Address, EepromKind::X24C02) => {
+                self.address = byte;
+                self.read_mode = false;
+            }
+            (EepromPhase::Write, _) => {
+                self.data[self.address as usize] = byte;
+
+                // Sequential writes wrap around within a page
+                let page_mask = self.page_mask();
+                self.address =
+                    (self.address & !page_mask) | (self.address.wrapping_add(1) & page_mask);
+            }
+            _ => {}
+        }
+
+        // Acknowledge the byte
+        self.output = false;
+    }
+}
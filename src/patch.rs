@@ -0,0 +1,463 @@
+use crate::util::crc32;
+use std::error::Error;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_END: &[u8] = b"EOF";
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+
+// UPS and BPS files end with the checksums of the source, the target and the patch itself
+const FOOTER_SIZE: usize = 12;
+
+// Larger targets can only come from corrupted sizes, they are rejected before allocating
+const MAX_TARGET_SIZE: usize = 0x0100_0000;
+
+#[derive(Debug)]
+pub enum PatchError {
+    /// The file is not an IPS, UPS or BPS patch
+    UnknownFormat,
+    /// The patch ends unexpectedly or contains invalid data
+    Corrupted,
+    /// The patch was made for a different ROM
+    SourceMismatch,
+    /// Applying the patch produced a different result than the one it was made with
+    TargetMismatch,
+}
+impl Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::UnknownFormat => f.write_str("Unknown patch format"),
+            PatchError::Corrupted => f.write_str("Patch file is corrupted"),
+            PatchError::SourceMismatch => f.write_str("Patch does not match the ROM"),
+            PatchError::TargetMismatch => f.write_str("Patched ROM has the wrong checksum"),
+        }
+    }
+}
+impl Error for PatchError {}
+
+struct PatchReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> PatchReader<'a> {
+    const fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    #[inline]
+    fn read_byte(&mut self) -> Result<u8, PatchError> {
+        let byte = *self.data.get(self.pos).ok_or(PatchError::Corrupted)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], PatchError> {
+        let end = self.pos.checked_add(count).ok_or(PatchError::Corrupted)?;
+        let bytes = self.data.get(self.pos..end).ok_or(PatchError::Corrupted)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Reads a big endian number of `count` bytes, as used by IPS
+    fn read_be(&mut self, count: usize) -> Result<usize, PatchError> {
+        let bytes = self.read_bytes(count)?;
+        Ok(bytes
+            .iter()
+            .fold(0, |value, &byte| (value << 8) | (byte as usize)))
+    }
+
+    /// Reads a variable length number as used by UPS and BPS
+    fn read_number(&mut self) -> Result<usize, PatchError> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.read_byte()?;
+            value = ((byte & 0x7F) as usize)
+                .checked_mul(shift)
+                .and_then(|data| value.checked_add(data))
+                .ok_or(PatchError::Corrupted)?;
+            if (byte & 0x80) != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(128).ok_or(PatchError::Corrupted)?;
+            value = value.checked_add(shift).ok_or(PatchError::Corrupted)?;
+        }
+    }
+}
+
+#[inline]
+fn read_crc(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Checks the footer shared by UPS and BPS and returns the expected target checksum
+fn check_footer(source: &[u8], patch: &[u8]) -> Result<u32, PatchError> {
+    let footer = patch.len() - FOOTER_SIZE;
+    if crc32(0, &patch[..(patch.len() - 4)]) != read_crc(patch, footer + 8) {
+        return Err(PatchError::Corrupted);
+    }
+    if crc32(0, source) != read_crc(patch, footer) {
+        return Err(PatchError::SourceMismatch);
+    }
+    Ok(read_crc(patch, footer + 4))
+}
+
+fn apply_ips(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut target = source.to_vec();
+    let mut reader = PatchReader::new(patch, IPS_MAGIC.len());
+    loop {
+        if reader.read_bytes(IPS_END.len())? == IPS_END {
+            break;
+        }
+        reader.pos -= IPS_END.len();
+
+        let offset = reader.read_be(3)?;
+        let size = reader.read_be(2)?;
+        let (size, bytes) = if size == 0 {
+            // Run-length encoded record
+            let size = reader.read_be(2)?;
+            let value = reader.read_byte()?;
+            (size, vec![value; size])
+        } else {
+            (size, reader.read_bytes(size)?.to_vec())
+        };
+
+        if target.len() < (offset + size) {
+            target.resize(offset + size, 0);
+        }
+        target[offset..(offset + size)].copy_from_slice(&bytes);
+    }
+
+    // Some patches truncate the file after the end marker
+    if let Ok(length) = reader.read_be(3) {
+        target.truncate(length);
+    }
+    Ok(target)
+}
+
+fn apply_ups(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_crc = check_footer(source, patch)?;
+
+    let mut reader = PatchReader::new(patch, UPS_MAGIC.len());
+    let source_size = reader.read_number()?;
+    let target_size = reader.read_number()?;
+    if source_size != source.len() {
+        return Err(PatchError::SourceMismatch);
+    }
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::Corrupted);
+    }
+
+    let mut target = source.to_vec();
+    target.resize(target_size, 0);
+
+    // Changes are stored as bytes that have to be XORed with the source
+    let end = patch.len() - FOOTER_SIZE;
+    let mut pos: usize = 0;
+    while reader.pos < end {
+        pos = pos
+            .checked_add(reader.read_number()?)
+            .ok_or(PatchError::Corrupted)?;
+        loop {
+            let byte = reader.read_byte()?;
+            if byte == 0 {
+                pos += 1;
+                break;
+            }
+            *target.get_mut(pos).ok_or(PatchError::Corrupted)? ^= byte;
+            pos += 1;
+        }
+    }
+
+    if crc32(0, &target) != target_crc {
+        return Err(PatchError::TargetMismatch);
+    }
+    Ok(target)
+}
+
+fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_crc = check_footer(source, patch)?;
+
+    let mut reader = PatchReader::new(patch, BPS_MAGIC.len());
+    let source_size = reader.read_number()?;
+    let target_size = reader.read_number()?;
+    let metadata_size = reader.read_number()?;
+    reader.read_bytes(metadata_size)?;
+    if source_size != source.len() {
+        return Err(PatchError::SourceMismatch);
+    }
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::Corrupted);
+    }
+
+    let read_offset = |reader: &mut PatchReader, offset: usize| -> Result<usize, PatchError> {
+        let data = reader.read_number()?;
+        let distance = data >> 1;
+        if (data & 0x01) != 0 {
+            offset.checked_sub(distance).ok_or(PatchError::Corrupted)
+        } else {
+            Ok(offset + distance)
+        }
+    };
+
+    let mut target: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+    let end = patch.len() - FOOTER_SIZE;
+    while reader.pos < end {
+        let data = reader.read_number()?;
+        let length = (data >> 2) + 1;
+        if length > (target_size - target.len()) {
+            return Err(PatchError::Corrupted);
+        }
+        match data & 0x03 {
+            // Source read
+            0 => {
+                let start = target.len();
+                let bytes = source
+                    .get(start..(start + length))
+                    .ok_or(PatchError::Corrupted)?;
+                target.extend_from_slice(bytes);
+            }
+            // Target read
+            1 => target.extend_from_slice(reader.read_bytes(length)?),
+            // Source copy
+            2 => {
+                source_offset = read_offset(&mut reader, source_offset)?;
+                let bytes = source
+                    .get(source_offset..(source_offset + length))
+                    .ok_or(PatchError::Corrupted)?;
+                target.extend_from_slice(bytes);
+                source_offset += length;
+            }
+            // Target copy, may overlap with the bytes it produces
+            3 => {
+                target_offset = read_offset(&mut reader, target_offset)?;
+                for _ in 0..length {
+                    let byte = *target.get(target_offset).ok_or(PatchError::Corrupted)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if (target.len() != target_size) || (crc32(0, &target) != target_crc) {
+        return Err(PatchError::TargetMismatch);
+    }
+    Ok(target)
+}
+
+/// Applies an IPS, UPS or BPS patch, the format is detected from the patch contents
+pub fn apply_patch(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(source, patch)
+    } else if patch.starts_with(UPS_MAGIC) && (patch.len() >= UPS_MAGIC.len() + FOOTER_SIZE) {
+        apply_ups(source, patch)
+    } else if patch.starts_with(BPS_MAGIC) && (patch.len() >= BPS_MAGIC.len() + FOOTER_SIZE) {
+        apply_bps(source, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+/// Finds a patch with the same base name as the ROM file
+pub fn find_patch<P: AsRef<Path>>(file: P) -> Option<PathBuf> {
+    ["ips", "ups", "bps"]
+        .iter()
+        .map(|extension| file.as_ref().with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The inverse of `PatchReader::read_number`
+    fn encode_number(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let data = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(data | 0x80);
+                return bytes;
+            }
+            bytes.push(data);
+            value -= 1;
+        }
+    }
+
+    /// Builds a UPS or BPS patch from its body, the checksums are appended
+    fn checked_patch(body: &[u8], source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = body.to_vec();
+        patch.extend_from_slice(&crc32(0, source).to_le_bytes());
+        patch.extend_from_slice(&crc32(0, target).to_le_bytes());
+        patch.extend_from_slice(&crc32(0, &patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn number_round_trip() {
+        for &value in [0, 1, 0x7F, 0x80, 0x407F, 0x4080, 0x0123_4567, usize::MAX].iter() {
+            let bytes = encode_number(value);
+            assert_eq!(PatchReader::new(&bytes, 0).read_number().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn number_overflow() {
+        let mut bytes = vec![0x7F; 10];
+        bytes.push(0xFF);
+        assert!(matches!(
+            PatchReader::new(&bytes, 0).read_number(),
+            Err(PatchError::Corrupted)
+        ));
+    }
+
+    #[test]
+    fn ips_records() {
+        #[rustfmt::skip]
+        let patch = [
+            b'P', b'A', b'T', b'C', b'H',
+            0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0xAA, // RLE record
+            0x00, 0x00, 0x07, 0x00, 0x02, 0x01, 0x02,       // Record past the end
+            b'E', b'O', b'F',
+        ];
+        let target = apply_patch(&[0; 8], &patch).unwrap();
+        assert_eq!(
+            target,
+            [0x00, 0x00, 0xAA, 0xAA, 0xAA, 0x00, 0x00, 0x01, 0x02]
+        );
+    }
+
+    #[test]
+    fn ips_truncation() {
+        #[rustfmt::skip]
+        let patch = [
+            b'P', b'A', b'T', b'C', b'H',
+            0x00, 0x00, 0x00, 0x00, 0x01, 0xFF,
+            b'E', b'O', b'F',
+            0x00, 0x00, 0x03, // Truncated size
+        ];
+        let target = apply_patch(&[1, 2, 3, 4, 5, 6], &patch).unwrap();
+        assert_eq!(target, [0xFF, 2, 3]);
+    }
+
+    #[test]
+    fn ups_xor_past_source_end() {
+        let source = [1, 2, 3, 4];
+        let target = [1, 2, 7, 4, 5, 6];
+
+        let mut body = UPS_MAGIC.to_vec();
+        body.extend(encode_number(source.len()));
+        body.extend(encode_number(target.len()));
+        // Skip two bytes and change one
+        body.extend(encode_number(2));
+        body.extend_from_slice(&[3 ^ 7, 0x00]);
+        // The bytes past the end of the source are XORed with zero
+        body.extend(encode_number(0));
+        body.extend_from_slice(&[5, 6, 0x00]);
+        let patch = checked_patch(&body, &source, &target);
+
+        assert_eq!(apply_patch(&source, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn bps_copies() {
+        let source = b"ABCDEFGH";
+        let target = b"ABFGHCDXCDXBFGH";
+
+        let command = |length: usize, action: usize| encode_number(((length - 1) << 2) | action);
+        let offset =
+            |distance: usize, negative: bool| encode_number((distance << 1) | (negative as usize));
+
+        let mut body = BPS_MAGIC.to_vec();
+        body.extend(encode_number(source.len()));
+        body.extend(encode_number(target.len()));
+        body.extend(encode_number(0));
+        // Source read: AB
+        body.extend(command(2, 0));
+        // Source copy forward: FGH
+        body.extend(command(3, 2));
+        body.extend(offset(5, false));
+        // Source copy backward: CD
+        body.extend(command(2, 2));
+        body.extend(offset(6, true));
+        // Target read: X
+        body.extend(command(1, 1));
+        body.push(b'X');
+        // Target copy forward: CDX
+        body.extend(command(3, 3));
+        body.extend(offset(5, false));
+        // Target copy backward: BFGH
+        body.extend(command(4, 3));
+        body.extend(offset(7, true));
+        let patch = checked_patch(&body, source, target);
+
+        assert_eq!(apply_patch(source, &patch).unwrap(), target);
+    }
+
+    /// A UPS patch that turns the first byte of a 4 byte ROM from 1 to 2
+    fn ups_patch(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut body = UPS_MAGIC.to_vec();
+        body.extend(encode_number(4));
+        body.extend(encode_number(4));
+        body.extend(encode_number(0));
+        body.extend_from_slice(&[1 ^ 2, 0x00]);
+        checked_patch(&body, source, target)
+    }
+
+    #[test]
+    fn checksum_mismatches() {
+        let source = [1, 0, 0, 0];
+        let target = [2, 0, 0, 0];
+        let patch = ups_patch(&source, &target);
+        assert_eq!(apply_patch(&source, &patch).unwrap(), target);
+
+        assert!(matches!(
+            apply_patch(&[3, 0, 0, 0], &patch),
+            Err(PatchError::SourceMismatch)
+        ));
+        assert!(matches!(
+            apply_patch(&source, &ups_patch(&source, &[4, 0, 0, 0])),
+            Err(PatchError::TargetMismatch)
+        ));
+
+        let mut damaged = patch;
+        damaged[UPS_MAGIC.len()] ^= 0x01;
+        assert!(matches!(
+            apply_patch(&source, &damaged),
+            Err(PatchError::Corrupted)
+        ));
+    }
+
+    #[test]
+    fn oversized_target() {
+        let source = [0; 4];
+        let mut body = UPS_MAGIC.to_vec();
+        body.extend(encode_number(source.len()));
+        body.extend(encode_number(usize::MAX));
+        let patch = checked_patch(&body, &source, &source);
+        assert!(matches!(
+            apply_patch(&source, &patch),
+            Err(PatchError::Corrupted)
+        ));
+
+        body[..BPS_MAGIC.len()].copy_from_slice(BPS_MAGIC);
+        body.extend(encode_number(0));
+        let patch = checked_patch(&body, &source, &source);
+        assert!(matches!(
+            apply_patch(&source, &patch),
+            Err(PatchError::Corrupted)
+        ));
+    }
+}
@@ -0,0 +1,365 @@
+use std::error::Error;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+const GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+#[derive(Debug)]
+pub enum CheatError {
+    Io(std::io::Error),
+    /// The code is neither a Game Genie nor a Pro Action Replay code
+    InvalidCode(String),
+}
+impl Display for CheatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheatError::Io(err) => err.fmt(f),
+            CheatError::InvalidCode(code) => {
+                f.write_fmt(format_args!("Invalid cheat code \"{}\"", code))
+            }
+        }
+    }
+}
+impl Error for CheatError {}
+impl From<std::io::Error> for CheatError {
+    fn from(err: std::io::Error) -> Self {
+        CheatError::Io(err)
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum CheatEffect {
+    /// Game Genie, replaces the value the CPU reads from cartridge space.
+    /// With a compare value the read is only replaced if the ROM contains that value.
+    Patch {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// Pro Action Replay, writes a value to memory once every frame
+    Write { address: u16, value: u8 },
+}
+impl CheatEffect {
+    /// Decodes a 6 or 8 letter Game Genie code
+    pub fn from_game_genie(code: &str) -> Option<Self> {
+        let n = code
+            .chars()
+            .map(|c| {
+                GAME_GENIE_LETTERS
+                    .find(c.to_ascii_uppercase())
+                    .map(|n| n as u16)
+            })
+            .collect::<Option<Vec<u16>>>()?;
+        if (n.len() != 6) && (n.len() != 8) {
+            return None;
+        }
+
+        let address = 0x8000
+            | ((n[3] & 7) << 12)
+            | ((n[5] & 7) << 8)
+            | ((n[4] & 8) << 8)
+            | ((n[2] & 7) << 4)
+            | ((n[1] & 8) << 4)
+            | (n[4] & 7)
+            | (n[3] & 8);
+        let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7);
+
+        if n.len() == 6 {
+            Some(CheatEffect::Patch {
+                address,
+                value: (value | (n[5] & 8)) as u8,
+                compare: None,
+            })
+        } else {
+            let compare = ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8);
+            Some(CheatEffect::Patch {
+                address,
+                value: (value | (n[7] & 8)) as u8,
+                compare: Some(compare as u8),
+            })
+        }
+    }
+
+    /// Decodes a Pro Action Replay code of the form `AAAA:VV`.
+    /// The colon is required, without it codes like `AEAEAE` would also be valid Game Genie codes.
+    pub fn from_action_replay(code: &str) -> Option<Self> {
+        let (address, value) = code.split_at(code.find(':')?);
+        let value = &value[1..];
+        if (address.len() != 4)
+            || (value.len() != 2)
+            || !address
+                .chars()
+                .chain(value.chars())
+                .all(|c| c.is_ascii_hexdigit())
+        {
+            return None;
+        }
+
+        Some(CheatEffect::Write {
+            address: u16::from_str_radix(address, 16).ok()?,
+            value: u8::from_str_radix(value, 16).ok()?,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Cheat {
+    code: String,
+    description: String,
+    effect: CheatEffect,
+    enabled: bool,
+}
+impl Cheat {
+    pub fn new(code: &str, description: &str) -> Result<Self, CheatError> {
+        let effect = CheatEffect::from_game_genie(code)
+            .or_else(|| CheatEffect::from_action_replay(code))
+            .ok_or_else(|| CheatError::InvalidCode(code.to_string()))?;
+
+        Ok(Self::from_effect(code, description, effect))
+    }
+
+    pub fn from_effect(code: &str, description: &str, effect: CheatEffect) -> Self {
+        Self {
+            code: code.to_uppercase(),
+            description: description.to_string(),
+            effect,
+            enabled: true,
+        }
+    }
+
+    #[inline]
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    #[inline]
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    #[inline]
+    pub const fn effect(&self) -> CheatEffect {
+        self.effect
+    }
+
+    #[inline]
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    #[inline]
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+}
+
+/// All cheats of the running game.
+/// Cheat files contain one code per line followed by an optional description,
+/// disabled codes start with `!` and lines starting with `#` are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct CheatList {
+    cheats: Vec<Cheat>,
+}
+impl CheatList {
+    #[inline]
+    pub const fn new() -> Self {
+        Self { cheats: Vec::new() }
+    }
+
+    pub fn load<P: AsRef<Path>>(file: P) -> Result<Self, CheatError> {
+        let text = std::fs::read_to_string(file)?;
+
+        let mut list = Self::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (enabled, line) = match line.strip_prefix('!') {
+                Some(line) => (false, line.trim_start()),
+                None => (true, line),
+            };
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let code = parts.next().unwrap_or_default();
+            let description = parts.next().unwrap_or_default().trim();
+
+            let mut cheat = Cheat::new(code, description)?;
+            cheat.set_enabled(enabled);
+            list.add(cheat);
+        }
+
+        Ok(list)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, file: P) -> Result<(), CheatError> {
+        let mut text = String::new();
+        for cheat in self.cheats.iter() {
+            if !cheat.enabled {
+                text.push('!');
+            }
+            text.push_str(&cheat.code);
+            if !cheat.description.is_empty() {
+                text.push(' ');
+                text.push_str(&cheat.description);
+            }
+            text.push('\n');
+        }
+
+        std::fs::write(file, text)?;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn add(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+    }
+
+    #[inline]
+    pub fn remove(&mut self, index: usize) -> Cheat {
+        self.cheats.remove(index)
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.cheats.clear();
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.cheats.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&Cheat> {
+        self.cheats.get(index)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Cheat> {
+        self.cheats.get_mut(index)
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &Cheat> {
+        self.cheats.iter()
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Cheat> {
+        self.cheats.iter_mut()
+    }
+
+    /// Applies all enabled Game Genie codes to a byte read from cartridge space
+    pub fn patch_read(&self, address: u16, data: u8) -> u8 {
+        for cheat in self.cheats.iter().filter(|cheat| cheat.enabled) {
+            if let CheatEffect::Patch {
+                address: patch_address,
+                value,
+                compare,
+            } = cheat.effect
+            {
+                if (patch_address == address) && compare.map_or(true, |compare| compare == data) {
+                    return value;
+                }
+            }
+        }
+
+        data
+    }
+
+    /// The memory writes of all enabled Pro Action Replay codes
+    pub fn writes(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .filter_map(|cheat| match cheat.effect {
+                CheatEffect::Write { address, value } => Some((address, value)),
+                _ => None,
+            })
+    }
+}
+
+/// The cheat file belonging to a ROM file
+#[inline]
+pub fn cheat_path<P: AsRef<Path>>(file: P) -> PathBuf {
+    file.as_ref().with_extension("cht")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_genie_six_letters() {
+        assert_eq!(
+            CheatEffect::from_game_genie("SXIOPO"),
+            Some(CheatEffect::Patch {
+                address: 0x91D9,
+                value: 0xAD,
+                compare: None,
+            })
+        );
+        assert_eq!(
+            CheatEffect::from_game_genie("gossip"),
+            Some(CheatEffect::Patch {
+                address: 0xD1DD,
+                value: 0x14,
+                compare: None,
+            })
+        );
+    }
+
+    #[test]
+    fn game_genie_eight_letters() {
+        assert_eq!(
+            CheatEffect::from_game_genie("ZEXPYGLA"),
+            Some(CheatEffect::Patch {
+                address: 0x94A7,
+                value: 0x02,
+                compare: Some(0x03),
+            })
+        );
+    }
+
+    #[test]
+    fn game_genie_invalid() {
+        assert_eq!(CheatEffect::from_game_genie("SXIOP"), None);
+        assert_eq!(CheatEffect::from_game_genie("SXIOPOB"), None);
+        assert_eq!(CheatEffect::from_game_genie("SXIOPQ"), None);
+    }
+
+    #[test]
+    fn action_replay() {
+        assert_eq!(
+            CheatEffect::from_action_replay("075A:09"),
+            Some(CheatEffect::Write {
+                address: 0x075A,
+                value: 0x09,
+            })
+        );
+        assert_eq!(CheatEffect::from_action_replay("075A09"), None);
+        assert_eq!(CheatEffect::from_action_replay("75A:09"), None);
+        assert_eq!(CheatEffect::from_action_replay("075A:9G"), None);
+    }
+
+    #[test]
+    fn hex_letters_are_game_genie() {
+        let cheat = Cheat::new("AEAEAE", "").unwrap();
+        assert!(matches!(cheat.effect(), CheatEffect::Patch { .. }));
+
+        let cheat = Cheat::new("AEAE:AE", "").unwrap();
+        assert_eq!(
+            cheat.effect(),
+            CheatEffect::Write {
+                address: 0xAEAE,
+                value: 0xAE,
+            }
+        );
+    }
+}
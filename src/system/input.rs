@@ -0,0 +1,437 @@
+use crate::EmuRef;
+
+bitflags! {
+    pub struct Buttons : u8 {
+        const A      = 0b10000000;
+        const B      = 0b01000000;
+        const SELECT = 0b00100000;
+        const START  = 0b00010000;
+        const UP     = 0b00001000;
+        const DOWN   = 0b00000100;
+        const LEFT   = 0b00000010;
+        const RIGHT  = 0b00000001;
+    }
+}
+
+bitflags! {
+    /// The data lines a device can drive when its port is read
+    pub struct InputLines : u8 {
+        const D0 = 0b00000001;
+        const D1 = 0b00000010;
+        const D2 = 0b00000100;
+        const D3 = 0b00001000;
+        const D4 = 0b00010000;
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Port {
+    One,
+    Two,
+    /// Famicom expansion port, sees reads of both $4016 and $4017
+    Expansion,
+}
+impl Port {
+    pub const ALL: [Port; 3] = [Port::One, Port::Two, Port::Expansion];
+
+    /// The controller port read through $4016 (0) or $4017 (1)
+    #[inline]
+    pub const fn from_register(register: usize) -> Self {
+        if register == 0 {
+            Port::One
+        } else {
+            Port::Two
+        }
+    }
+
+    #[inline]
+    pub const fn index(self) -> usize {
+        match self {
+            Port::One => 0,
+            Port::Two => 1,
+            Port::Expansion => 2,
+        }
+    }
+}
+
+/// Something that can be plugged into a controller or expansion port
+pub trait InputDevice {
+    fn name(&self) -> &'static str;
+
+    /// Called on writes to $4016 with the OUT0-OUT2 lines, OUT0 is the strobe
+    fn write(&mut self, out: u8);
+
+    /// Called on reads of $4016 (`register` 0) or $4017 (`register` 1)
+    fn read(&mut self, register: usize) -> InputLines;
+}
+
+/// Gives light guns access to the picture the PPU is currently drawing
+pub trait LightSource {
+    /// Pixel coordinates the PPU outputs next, `y` is outside the screen during blanking
+    fn beam_position(&self) -> (isize, isize);
+
+    /// Brightness of a pixel from 0 to 255, pixels outside the screen are dark
+    fn brightness(&self, x: isize, y: isize) -> u8;
+}
+
+/// Shift register of an official controller, reads return 1 once all bits are shifted out
+#[derive(Clone, Copy, Debug)]
+struct ShiftRegister {
+    value: u32,
+    remaining: u32,
+}
+impl ShiftRegister {
+    #[inline]
+    const fn new() -> Self {
+        Self {
+            value: 0,
+            remaining: 0,
+        }
+    }
+
+    #[inline]
+    fn load(&mut self, value: u32, bits: u32) {
+        self.value = value;
+        self.remaining = bits;
+    }
+
+    /// Shifts out the lowest bit
+    #[inline]
+    fn shift(&mut self) -> bool {
+        if self.remaining == 0 {
+            true
+        } else {
+            let bit = (self.value & 0x01) != 0;
+            self.value >>= 1;
+            self.remaining -= 1;
+            bit
+        }
+    }
+}
+
+/// Reverses the button order, the controller sends A first
+#[inline]
+const fn button_bits(buttons: Buttons) -> u32 {
+    buttons.bits().reverse_bits() as u32
+}
+
+/// The standard NES controller
+pub struct StandardController {
+    buttons: Buttons,
+    register: ShiftRegister,
+    strobe: bool,
+}
+impl StandardController {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            buttons: Buttons::empty(),
+            register: ShiftRegister::new(),
+            strobe: false,
+        }
+    }
+
+    #[inline]
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.buttons = buttons;
+    }
+}
+impl InputDevice for StandardController {
+    #[inline]
+    fn name(&self) -> &'static str {
+        "Controller"
+    }
+
+    fn write(&mut self, out: u8) {
+        self.strobe = (out & 0x01) != 0;
+        if self.strobe {
+            self.register.load(button_bits(self.buttons), 8);
+        }
+    }
+
+    fn read(&mut self, _register: usize) -> InputLines {
+        // While strobe is high the buttons are reloaded constantly
+        if self.strobe {
+            self.register.load(button_bits(self.buttons), 8);
+        }
+
+        if self.register.shift() {
+            InputLines::D0
+        } else {
+            InputLines::empty()
+        }
+    }
+}
+
+/// Four Score adapter, has to be plugged into both ports.
+/// Each port reports two controllers followed by a signature.
+pub struct FourScore {
+    buttons: [Buttons; 4],
+    registers: [ShiftRegister; 2],
+    strobe: bool,
+}
+impl FourScore {
+    const SIGNATURES: [u32; 2] = [0b00001000, 0b00000100];
+
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            buttons: [Buttons::empty(); 4],
+            registers: [ShiftRegister::new(); 2],
+            strobe: false,
+        }
+    }
+
+    #[inline]
+    pub fn set_buttons(&mut self, controller: usize, buttons: Buttons) {
+        self.buttons[controller] = buttons;
+    }
+
+    fn reload(&mut self) {
+        for (index, register) in self.registers.iter_mut().enumerate() {
+            let value = button_bits(self.buttons[index])
+                | (button_bits(self.buttons[index + 2]) << 8)
+                | (Self::SIGNATURES[index] << 16);
+            register.load(value, 24);
+        }
+    }
+}
+impl InputDevice for FourScore {
+    #[inline]
+    fn name(&self) -> &'static str {
+        "Four Score"
+    }
+
+    fn write(&mut self, out: u8) {
+        self.strobe = (out & 0x01) != 0;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    fn read(&mut self, register: usize) -> InputLines {
+        if self.strobe {
+            self.reload();
+        }
+
+        if self.registers[register & 0x01].shift() {
+            InputLines::D0
+        } else {
+            InputLines::empty()
+        }
+    }
+}
+
+/// Arkanoid controller, a knob with a fire button
+pub struct ArkanoidPaddle {
+    position: u8,
+    button: bool,
+    register: ShiftRegister,
+}
+impl ArkanoidPaddle {
+    /// Range of the knob on the NES version
+    pub const MIN_POSITION: u8 = 98;
+    pub const MAX_POSITION: u8 = 242;
+
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            position: Self::MIN_POSITION,
+            button: false,
+            register: ShiftRegister::new(),
+        }
+    }
+
+    #[inline]
+    pub fn set_position(&mut self, position: u8) {
+        self.position = position.clamp(Self::MIN_POSITION, Self::MAX_POSITION);
+    }
+
+    #[inline]
+    pub fn set_button(&mut self, pressed: bool) {
+        self.button = pressed;
+    }
+}
+impl InputDevice for ArkanoidPaddle {
+    #[inline]
+    fn name(&self) -> &'static str {
+        "Arkanoid Controller"
+    }
+
+    fn write(&mut self, out: u8) {
+        // The knob position is sent MSB first and inverted
+        if (out & 0x01) != 0 {
+            self.register
+                .load((!self.position).reverse_bits() as u32, 8);
+        }
+    }
+
+    fn read(&mut self, _register: usize) -> InputLines {
+        let mut lines = InputLines::empty();
+        if self.button {
+            lines |= InputLines::D3;
+        }
+        // Unlike the controller it returns zeros after all bits are read
+        if (self.register.remaining > 0) && self.register.shift() {
+            lines |= InputLines::D4;
+        }
+        lines
+    }
+}
+
+/// Power Pad mat, buttons are numbered from 1 to 12
+pub struct PowerPad {
+    buttons: u16,
+    registers: [ShiftRegister; 2],
+    strobe: bool,
+}
+impl PowerPad {
+    // Order in which the buttons are sent on D3 and D4
+    const D3_ORDER: [u32; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+    const D4_ORDER: [u32; 4] = [4, 3, 12, 8];
+
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            buttons: 0,
+            registers: [ShiftRegister::new(); 2],
+            strobe: false,
+        }
+    }
+
+    #[inline]
+    pub fn set_button(&mut self, button: u32, pressed: bool) {
+        let mask = 1 << (button - 1);
+        if pressed {
+            self.buttons |= mask;
+        } else {
+            self.buttons &= !mask;
+        }
+    }
+
+    fn serialize(&self, order: &[u32]) -> u32 {
+        order
+            .iter()
+            .enumerate()
+            .filter(|(_, &button)| (self.buttons & (1 << (button - 1))) != 0)
+            .fold(0, |value, (bit, _)| value | (1 << bit))
+    }
+
+    fn reload(&mut self) {
+        let d3 = self.serialize(&Self::D3_ORDER);
+        let d4 = self.serialize(&Self::D4_ORDER);
+        self.registers[0].load(d3, Self::D3_ORDER.len() as u32);
+        self.registers[1].load(d4, Self::D4_ORDER.len() as u32);
+    }
+}
+impl InputDevice for PowerPad {
+    #[inline]
+    fn name(&self) -> &'static str {
+        "Power Pad"
+    }
+
+    fn write(&mut self, out: u8) {
+        self.strobe = (out & 0x01) != 0;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    fn read(&mut self, _register: usize) -> InputLines {
+        if self.strobe {
+            self.reload();
+        }
+
+        let mut lines = InputLines::empty();
+        if self.registers[0].shift() {
+            lines |= InputLines::D3;
+        }
+        if self.registers[1].shift() {
+            lines |= InputLines::D4;
+        }
+        lines
+    }
+}
+
+/// Zapper light gun, reports whether the screen around the cursor was lit recently
+pub struct Zapper<'a> {
+    light_source: EmuRef<dyn LightSource + 'a>,
+    position: Option<(isize, isize)>,
+    trigger: bool,
+}
+impl<'a> Zapper<'a> {
+    /// The photodiode sees a few pixels around the point it is aimed at
+    const SENSOR_RADIUS: isize = 2;
+    /// How many scanlines the photodiode keeps reporting light after a bright pixel was drawn
+    const LIGHT_SCANLINES: isize = 20;
+    const BRIGHTNESS_THRESHOLD: u8 = 128;
+
+    #[inline]
+    pub fn new(light_source: EmuRef<dyn LightSource + 'a>) -> Self {
+        Self {
+            light_source,
+            position: None,
+            trigger: false,
+        }
+    }
+
+    /// The screen pixel the Zapper is aimed at, `None` if it points away from the screen
+    #[inline]
+    pub fn set_position(&mut self, position: Option<(isize, isize)>) {
+        self.position = position;
+    }
+
+    #[inline]
+    pub fn set_trigger(&mut self, pulled: bool) {
+        self.trigger = pulled;
+    }
+
+    fn light_detected(&self) -> bool {
+        let (x, y) = match self.position {
+            Some(position) => position,
+            None => return false,
+        };
+        // The PPU may be busy if the read happens while it is being clocked
+        let source = match self.light_source.try_borrow() {
+            Ok(source) => source,
+            Err(_) => return false,
+        };
+
+        let (beam_x, beam_y) = source.beam_position();
+        for pixel_y in (y - Self::SENSOR_RADIUS)..=(y + Self::SENSOR_RADIUS) {
+            for pixel_x in (x - Self::SENSOR_RADIUS)..=(x + Self::SENSOR_RADIUS) {
+                // Only pixels that were drawn shortly before in this frame are visible
+                let drawn = (beam_y > pixel_y) || ((beam_y == pixel_y) && (beam_x > pixel_x));
+                if drawn
+                    && ((beam_y - pixel_y) < Self::LIGHT_SCANLINES)
+                    && (source.brightness(pixel_x, pixel_y) >= Self::BRIGHTNESS_THRESHOLD)
+                {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+impl<'a> InputDevice for Zapper<'a> {
+    #[inline]
+    fn name(&self) -> &'static str {
+        "Zapper"
+    }
+
+    #[inline]
+    fn write(&mut self, _out: u8) {}
+
+    fn read(&mut self, _register: usize) -> InputLines {
+        // The light sense line is low while light is detected
+        let mut lines = InputLines::empty();
+        if !self.light_detected() {
+            lines |= InputLines::D3;
+        }
+        if self.trigger {
+            lines |= InputLines::D4;
+        }
+        lines
+    }
+}
@@ -1 +1,5 @@
+pub mod cheats;
+pub mod input;
 pub mod nes;
+pub mod nesdb;
+pub mod ramsearch;
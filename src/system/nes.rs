@@ -1,20 +1,89 @@
+use crate::archive::read_file;
 use crate::audio::apu2A03::{Apu2A03, Apu2A03Control, Apu2A03FrameCounter};
+use crate::audio::mmc5::Mmc5Audio;
+use crate::audio::namco163::Namco163Audio;
+use crate::audio::sunsoft5b::Sunsoft5bAudio;
+use crate::audio::vrc6::Vrc6Audio;
+use crate::audio::vrc7::Vrc7Audio;
 use crate::audio::*;
 use crate::bus::*;
 use crate::cpu::cpu6502::Cpu6502;
 use crate::cpu::*;
-use crate::memory::Ram;
-use crate::util::BinReader;
+use crate::memory::{Eeprom, EepromKind, Ram, RamInit};
+use crate::patch::{apply_patch, find_patch, PatchError};
+use crate::system::cheats::CheatList;
+use crate::system::input::{InputDevice, InputLines, LightSource, Port};
+use crate::system::nesdb::find_game;
+use crate::system::ramsearch::MemorySnapshot;
+use crate::util::{crc32, BinReader};
 use crate::video::ppu2C02::Ppu2C02;
 use crate::video::*;
 use crate::*;
-use std::cell::Ref;
-use std::path::Path;
+use std::cell::{Ref, RefMut};
+use std::path::{Path, PathBuf};
 
 pub const NES_BASE_CLOCK: u32 = 21477272; // 21.47727 MHz
-pub const NES_CPU_CLOCK: u32 = NES_BASE_CLOCK / 12;
-pub const NES_PPU_CLOCK: u32 = NES_BASE_CLOCK / 4;
-pub const NES_APU_CLOCK: u32 = NES_CPU_CLOCK / 2;
+pub const NES_PAL_BASE_CLOCK: u32 = 26601712; // 26.601712 MHz
+
+/// The TV system the console was built for, it determines clock rates and frame timing
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    /// Famiclones with PAL frame rate but NTSC-like CPU/PPU ratio
+    Dendy,
+}
+impl Region {
+    #[inline]
+    pub const fn base_clock(self) -> u32 {
+        match self {
+            Region::Ntsc => NES_BASE_CLOCK,
+            Region::Pal | Region::Dendy => NES_PAL_BASE_CLOCK,
+        }
+    }
+
+    /// Number of master clock cycles per CPU cycle
+    #[inline]
+    pub const fn cpu_divider(self) -> u32 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    /// Number of master clock cycles per PPU cycle
+    #[inline]
+    pub const fn ppu_divider(self) -> u32 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    #[inline]
+    pub const fn cpu_clock(self) -> u32 {
+        self.base_clock() / self.cpu_divider()
+    }
+
+    #[inline]
+    pub const fn ppu_clock(self) -> u32 {
+        self.base_clock() / self.ppu_divider()
+    }
+
+    #[inline]
+    pub const fn apu_clock(self) -> u32 {
+        self.cpu_clock() / 2
+    }
+
+    #[inline]
+    pub const fn frame_rate(self) -> u32 {
+        match self {
+            Region::Ntsc => 60,
+            Region::Pal | Region::Dendy => 50,
+        }
+    }
+}
 
 #[allow(dead_code)]
 pub struct Nes<'a> {
@@ -25,7 +94,7 @@ pub struct Nes<'a> {
     apu_control: EmuRef<Apu2A03Control<'a>>,
     apu_frame_counter: EmuRef<Apu2A03FrameCounter<'a>>,
     dma: EmuRef<DmaInterface>,
-    controller: EmuRef<VController>,
+    controller: EmuRef<ControllerPorts<'a>>,
 
     ppu: EmuRef<Ppu2C02<'a>>,
     ppu_bus: EmuRef<Bus<'a, ppu2C02::Address, ppu2C02::Word>>,
@@ -35,8 +104,13 @@ pub struct Nes<'a> {
     cartridge: Option<EmuRef<Cartridge>>,
     cartridge_cpu_handle: Option<BusHandle>,
     cartridge_ppu_handle: Option<BusHandle>,
+    cheats: EmuRef<CheatList>,
 
-    cycle_even: bool,
+    region: Region,
+    ram_init: RamInit,
+    cpu_cycle: u64,
+    // Master clock cycles that have not yet added up to a full PPU cycle
+    ppu_clock_remainder: u32,
 }
 impl<'a> Nes<'a> {
     pub fn new() -> Self {
@@ -96,7 +170,7 @@ impl<'a> Nes<'a> {
         let dma = DmaInterface::create(DMA_ADDRESS);
         let dma_clone = clone_ref(&dma);
 
-        let controller = VController::create(CONTROLLER_START_ADDRESS);
+        let controller = ControllerPorts::create(CONTROLLER_START_ADDRESS);
         let controller_clone = clone_ref(&controller);
 
         {
@@ -129,10 +203,26 @@ impl<'a> Nes<'a> {
             cartridge: None,
             cartridge_cpu_handle: None,
             cartridge_ppu_handle: None,
-            cycle_even: true,
+            cheats: make_ref(CheatList::new()),
+            region: Region::Ntsc,
+            ram_init: RamInit::default(),
+            cpu_cycle: 0,
+            ppu_clock_remainder: 0,
         }
     }
 
+    #[inline]
+    pub const fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu_clock_remainder = 0;
+        self.ppu.borrow_mut().set_region(region);
+        self.apu.borrow_mut().set_region(region);
+    }
+
     pub fn set_cartridge(&mut self, cartridge: EmuRef<Cartridge>) {
         {
             let cartridge_borrow = cartridge.borrow();
@@ -146,9 +236,13 @@ impl<'a> Nes<'a> {
                     .borrow_mut()
                     .add_component(cartridge_borrow.get_ppu_adapter()),
             );
+            cartridge_borrow.set_vram(Some(clone_ref(&self.vram)));
+            cartridge_borrow.set_cheats(Some(clone_ref(&self.cheats)));
+            self.set_region(cartridge_borrow.region());
         }
         self.vram.borrow_mut().set_cartridge(clone_ref(&cartridge));
         self.ppu.borrow_mut().set_cartridge(clone_ref(&cartridge));
+        self.apu.borrow_mut().set_cartridge(clone_ref(&cartridge));
         self.cartridge = Some(cartridge);
     }
 
@@ -159,14 +253,75 @@ impl<'a> Nes<'a> {
         if let Some(handle) = self.cartridge_ppu_handle {
             self.ppu_bus.borrow_mut().remove_component(handle);
         }
+        if let Some(cartridge) = &self.cartridge {
+            // Breaks the reference cycle between the cartridge and VRAM
+            cartridge.borrow().set_vram(None);
+            cartridge.borrow().set_cheats(None);
+        }
         self.vram.borrow_mut().remove_cartridge();
         self.ppu.borrow_mut().remove_cartridge();
+        self.apu.borrow_mut().remove_cartridge();
 
         self.cartridge = None;
         self.cartridge_cpu_handle = None;
         self.cartridge_ppu_handle = None;
     }
 
+    /// Cheats are kept when the cartridge is changed
+    #[inline]
+    pub fn cheats(&self) -> RefMut<CheatList> {
+        self.cheats.borrow_mut()
+    }
+
+    #[inline]
+    pub fn set_cheats(&mut self, cheats: CheatList) {
+        *self.cheats.borrow_mut() = cheats;
+    }
+
+    /// Performs the writes of Pro Action Replay codes
+    fn apply_cheat_writes(&self) {
+        let cheats = self.cheats.borrow();
+        let cpu_bus = self.cpu_bus.borrow();
+        for (address, value) in cheats.writes() {
+            cpu_bus.write(Wrapping(address), Wrapping(value));
+        }
+    }
+
+    /// Copies internal RAM and the cartridge's PRG RAM for searching
+    pub fn memory_snapshot(&self) -> MemorySnapshot {
+        let ram: Vec<u8> = self.ram.borrow().data().iter().map(|data| data.0).collect();
+        let prg_ram = self
+            .cartridge
+            .as_ref()
+            .and_then(|cartridge| cartridge.borrow().prg_ram_window());
+        MemorySnapshot::new(&ram, prg_ram.as_deref())
+    }
+
+    #[inline]
+    pub const fn ram_init(&self) -> RamInit {
+        self.ram_init
+    }
+
+    /// Sets the pattern memory is filled with on the next power cycle
+    #[inline]
+    pub fn set_ram_init(&mut self, pattern: RamInit) {
+        self.ram_init = pattern;
+    }
+
+    /// Turns the console off and on again, unlike `reset` this also clears
+    /// CPU RAM, VRAM, OAM and palette to the configured pattern
+    pub fn power_cycle(&mut self) {
+        let pattern = self.ram_init;
+        self.ram.borrow_mut().fill(pattern, 0);
+        self.vram.borrow_mut().fill(pattern, 1);
+        self.palette.borrow_mut().fill(pattern, 3);
+        self.ppu.borrow_mut().fill_oam(pattern, 4);
+        self.cpu_cycle = 0;
+        self.ppu_clock_remainder = 0;
+        self.reset();
+    }
+
+    /// Presses the reset button, memory keeps its contents
     pub fn reset(&mut self) {
         self.cpu.reset();
         self.ppu.borrow_mut().reset();
@@ -183,14 +338,132 @@ impl<'a> Nes<'a> {
         Ref::map(self.ppu.borrow(), |ppu| ppu.get_buffer())
     }
 
+    /// The PPU output, for light guns
+    #[inline]
+    pub fn light_source(&self) -> EmuRef<dyn LightSource + 'a> {
+        let ppu: EmuRef<Ppu2C02<'a>> = clone_ref(&self.ppu);
+        ppu
+    }
+
+    /// Plugs a device into a port, replacing the one that was connected before.
+    /// Devices that use both controller ports have to be connected to both.
+    #[inline]
+    pub fn connect(&mut self, port: Port, device: EmuRef<dyn InputDevice + 'a>) {
+        self.controller.borrow_mut().devices[port.index()] = Some(device);
+    }
+
+    #[inline]
+    pub fn disconnect(&mut self, port: Port) -> Option<EmuRef<dyn InputDevice + 'a>> {
+        self.controller.borrow_mut().devices[port.index()].take()
+    }
+
+    #[inline]
+    pub fn device(&self, port: Port) -> Option<EmuRef<dyn InputDevice + 'a>> {
+        self.controller.borrow().devices[port.index()]
+            .as_ref()
+            .map(clone_ref)
+    }
+
+    /// Advances everything except the CPU by a number of CPU cycles
+    fn clock_components(&mut self, cpu_cycles: u32, buffer: &mut SampleBuffer) {
+        self.cpu_cycle += cpu_cycles as u64;
+
+        if let Some(cartridge) = &self.cartridge {
+            cartridge.borrow_mut().clock(cpu_cycles);
+        }
+        self.apu.borrow_mut().clock(cpu_cycles, buffer);
+
+        let master_cycles = cpu_cycles * self.region.cpu_divider() + self.ppu_clock_remainder;
+        let ppu_cycles = master_cycles / self.region.ppu_divider();
+        self.ppu_clock_remainder = master_cycles % self.region.ppu_divider();
+        self.ppu.borrow_mut().clock(ppu_cycles);
+    }
+
+    /// DMA units can only read on even ("get") cycles and write on odd ("put") cycles
     #[inline]
-    pub fn update_input_state(&mut self, controller_0: Buttons, controller_1: Buttons) {
-        self.controller
-            .borrow_mut()
-            .update_state(controller_0, controller_1);
+    const fn is_get_cycle(&self) -> bool {
+        (self.cpu_cycle % 2) == 0
+    }
+
+    /// Copies a page of memory into OAM while the CPU is halted
+    fn run_oam_dma(&mut self, page: u8, buffer: &mut SampleBuffer) {
+        let address = (page as u16) << 8;
+
+        // Halt cycle, plus one more if the first read would not land on a get cycle
+        self.clock_components(1, buffer);
+        if !self.is_get_cycle() {
+            self.clock_components(1, buffer);
+        }
+
+        let mut read_index: u16 = 0;
+        let mut write_index: u8 = 0;
+        let mut data: Option<cpu6502::Word> = None;
+        while (read_index < 256) || data.is_some() {
+            if self.is_get_cycle() {
+                let dmc_pending = self.apu.borrow().dmc_dma_pending();
+                if dmc_pending {
+                    // The DMC takes over the get cycle, the following put cycle is wasted
+                    self.apu.borrow_mut().dmc_dma();
+                } else if read_index < 256 {
+                    data = Some(self.cpu_bus.borrow().read(Wrapping(address | read_index)));
+                    read_index += 1;
+                }
+            } else if let Some(value) = data.take() {
+                self.ppu
+                    .borrow_mut()
+                    .dma_write(Wrapping(write_index), value);
+                write_index = write_index.wrapping_add(1);
+            }
+
+            self.clock_components(1, buffer);
+        }
+
+        // A DMC request at the very end finds the CPU still halted, so only its get cycle is added
+        let dmc_pending = self.apu.borrow().dmc_dma_pending();
+        if dmc_pending {
+            self.apu.borrow_mut().dmc_dma();
+            self.clock_components(1, buffer);
+        }
+    }
+
+    /// Halts the CPU to fetch the next DMC sample byte.
+    /// A CPU halted on a read repeats it, so registers like $2007 and $4016 see one extra read.
+    fn run_dmc_dma(&mut self, halted_read: Option<cpu6502::Address>, buffer: &mut SampleBuffer) {
+        // Halt and dummy cycle, plus an alignment cycle if needed
+        self.clock_components(1, buffer);
+        if let Some(address) = halted_read {
+            self.cpu_bus.borrow().read(address);
+        }
+        self.clock_components(1, buffer);
+        if !self.is_get_cycle() {
+            self.clock_components(1, buffer);
+        }
+
+        self.apu.borrow_mut().dmc_dma();
+        self.clock_components(1, buffer);
     }
 
     pub fn next_instruction(&mut self, buffer: &mut SampleBuffer) {
+        let oam_dma_page = {
+            let mut dma = self.dma.borrow_mut();
+            if dma.active {
+                dma.active = false;
+                Some(dma.page.0)
+            } else {
+                None
+            }
+        };
+        if let Some(page) = oam_dma_page {
+            self.run_oam_dma(page, buffer);
+            return;
+        }
+
+        let dmc_pending = self.apu.borrow().dmc_dma_pending();
+        if dmc_pending {
+            self.run_dmc_dma(None, buffer);
+            return;
+        }
+
         let nmi = { self.ppu.borrow_mut().check_nmi() };
 
         let irq = if let Some(cartridge) = &self.cartridge {
@@ -209,44 +482,42 @@ impl<'a> Nes<'a> {
             apu_borrow.irq_requested() || apu_borrow.dmc_irq_requested()
         };
 
-        let mut dma = self.dma.borrow_mut();
-        let cpu_cycles = if dma.active {
-            dma.active = false;
-            let address = (dma.page.0 as u16) << 8;
-            std::mem::drop(dma);
-
-            let cpu_bus_borrow = self.cpu_bus.borrow();
-            let mut ppu_borrow = self.ppu.borrow_mut();
-            for i in 0..256u16 {
-                let data = cpu_bus_borrow.read(Wrapping(address | i));
-                ppu_borrow.dma_write(Wrapping(i as u8), data);
+        let cpu_cycles = if nmi {
+            self.cpu.nmi()
+        } else if irq || apu_irq {
+            match self.cpu.irq() {
+                // Interrupts are masked, level triggered sources stay asserted until acknowledged
+                0 => self.cpu.execute_next_instruction(),
+                cycles => cycles,
             }
-
-            512 + if self.cycle_even { 0 } else { 1 }
         } else {
-            std::mem::drop(dma);
-
-            if nmi {
-                self.cpu.nmi()
-            } else if irq || apu_irq {
-                self.cpu.irq()
-            } else {
-                self.cpu.execute_next_instruction()
-            }
+            self.cpu.execute_next_instruction()
         };
 
-        self.cycle_even = self.cycle_even & ((cpu_cycles % 2) == 0);
-
-        self.apu.borrow_mut().clock(cpu_cycles, buffer);
-
-        let ppu_cycles = cpu_cycles * 3;
-        self.ppu.borrow_mut().clock(ppu_cycles);
+        // The instruction already ran as a whole, its cycles are replayed one by one
+        // so a DMC request can steal cycles from the middle of it
+        let last_read = self.cpu_bus.borrow().last_read_address();
+        for cycle in 1..=cpu_cycles {
+            self.clock_components(1, buffer);
+
+            let dmc_pending = (cycle < cpu_cycles) && self.apu.borrow().dmc_dma_pending();
+            if dmc_pending {
+                if cycle + 1 < cpu_cycles {
+                    self.run_dmc_dma(None, buffer);
+                } else if let Some(address) = last_read {
+                    // The halt lands on the final read, writes can't be halted and delay the DMA
+                    self.run_dmc_dma(Some(address), buffer);
+                }
+            }
+        }
     }
 
     #[inline]
     pub fn next_frame(&mut self, buffer: &mut SampleBuffer) {
         let buffer_length_before = buffer.len();
-        while (buffer.len() - buffer_length_before) < ((SAMPLE_RATE / FRAME_RATE) as usize) {
+        let samples_per_frame = (SAMPLE_RATE / self.region.frame_rate()) as usize;
+        self.apply_cheat_writes();
+        while (buffer.len() - buffer_length_before) < samples_per_frame {
             self.next_instruction(buffer);
         }
     }
@@ -278,6 +549,7 @@ impl<'a> Display for Nes<'a> {
 
 const PRG_BANK_SIZE: usize = 0x4000;
 const CHR_BANK_SIZE: usize = 0x2000;
+const NAMETABLE_RAM_SIZE: usize = 0x0800;
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum MirrorMode {
@@ -285,29 +557,169 @@ pub enum MirrorMode {
     Vertical,
     OneScreenLow,
     OneScreenHigh,
+    /// Two of the nametables are stored in extra VRAM on the cartridge
+    FourScreen,
 }
 
+#[derive(PartialEq, Eq, Debug)]
 enum MapperReadResult {
     Data(cpu6502::Word),
     Address(Option<usize>),
+    /// One of the two 1k nametables inside the console mapped into pattern table space
+    Ciram(usize),
+}
+
+/// Memory a nametable is read from
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Nametable {
+    /// One of the two 1k tables inside the console
+    Ciram(usize),
+    /// Memory supplied by the mapper
+    Mapper,
+    /// A 1k bank of CHR ROM
+    Chr(usize),
+    /// One of the 1k tables of extra VRAM on the cartridge
+    Cartridge(usize),
+}
+
+/// The kind of data the PPU is currently fetching
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PpuFetch {
+    Background,
+    Sprite,
+    /// Access through PPUDATA
+    Cpu,
 }
 
 trait Mapper {
     fn mirror(&self) -> Option<MirrorMode>;
 
+    /// Writes to ROM on boards with bus conflicts only see bits that are also set in the ROM
+    fn has_bus_conflicts(&self) -> bool {
+        false
+    }
+
+    /// Overrides the mirror mode for a single one of the four nametables
+    fn nametable(&self, _index: usize) -> Option<Nametable> {
+        None
+    }
+
     fn interrupt_state(&self) -> bool;
 
     fn reset_interrupt(&mut self);
 
     fn on_scanline(&mut self);
 
-    fn cpu_read(&self, addr: cpu6502::Address) -> MapperReadResult;
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult;
 
-    fn ppu_read(&self, addr: ppu2C02::Address) -> MapperReadResult;
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult;
 
     fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word);
 
     fn reset(&mut self);
+
+    /// Called on every nametable read, the data read from the console's VRAM is passed in
+    fn nametable_read(
+        &mut self,
+        _addr: ppu2C02::Address,
+        ciram_data: ppu2C02::Word,
+    ) -> ppu2C02::Word {
+        ciram_data
+    }
+
+    /// Called on writes to nametables that are supplied by the mapper
+    fn nametable_write(&mut self, _addr: ppu2C02::Address, _data: ppu2C02::Word) {}
+
+    fn set_ppu_fetch(&mut self, _fetch: PpuFetch) {}
+
+    /// Allows the mapper to snoop writes to the PPU registers
+    fn on_ppu_register_write(&mut self, _addr: cpu6502::Address, _data: cpu6502::Word) {}
+
+    /// Called for every address the PPU puts on its bus, `ppu_cycle` counts PPU cycles since power on
+    fn on_ppu_address(&mut self, _addr: ppu2C02::Address, _ppu_cycle: u64) {}
+
+    /// Maps pattern table accesses to CHR RAM on boards that have it next to CHR ROM
+    fn chr_ram_address(&self, _addr: ppu2C02::Address) -> Option<usize> {
+        None
+    }
+
+    /// Size of extra VRAM on the board that the mapper selects as `Nametable::Cartridge`,
+    /// four-screen boards get at least the 2k the two missing nametables need
+    fn nametable_ram_size(&self) -> usize {
+        0
+    }
+
+    /// CHR RAM of the board if the header doesn't specify it, boards without CHR ROM have 8k
+    fn default_chr_ram_size(&self, chr_rom_size: usize) -> usize {
+        if chr_rom_size == 0 {
+            CHR_BANK_SIZE
+        } else {
+            0
+        }
+    }
+
+    /// Work RAM of the board, `None` if it has none
+    fn prg_ram(&mut self) -> Option<&mut PrgRam> {
+        None
+    }
+
+    /// Contents of battery backed memory that should persist between sessions
+    fn save_data(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn load_save_data(&mut self, _data: &[u8]) {}
+
+    /// Advances the mapper by a number of CPU cycles
+    fn clock(&mut self, _cycles: u32) {}
+
+    /// Output of expansion audio hardware on the cartridge
+    fn audio_sample(&mut self) -> Sample {
+        0.0
+    }
+}
+
+/// Work RAM on the cartridge, smaller chips are mirrored and a size of 0 leaves the bus open
+struct PrgRam {
+    data: Box<[Wrapping<u8>]>,
+}
+impl PrgRam {
+    /// Most boards with work RAM have a single 8k chip
+    const DEFAULT_SIZE: usize = 0x2000;
+
+    #[inline]
+    fn new(size: usize) -> Self {
+        Self {
+            data: vec![Wrapping(0); size].into_boxed_slice(),
+        }
+    }
+
+    #[inline]
+    fn read(&self, index: usize) -> MapperReadResult {
+        if self.data.is_empty() {
+            MapperReadResult::Address(None)
+        } else {
+            MapperReadResult::Data(self.data[index % self.data.len()])
+        }
+    }
+
+    #[inline]
+    fn write(&mut self, index: usize, data: cpu6502::Word) {
+        if !self.data.is_empty() {
+            let size = self.data.len();
+            self.data[index % size] = data;
+        }
+    }
+
+    #[inline]
+    fn data(&self) -> &[Wrapping<u8>] {
+        &self.data
+    }
+
+    #[inline]
+    fn data_mut(&mut self) -> &mut [Wrapping<u8>] {
+        &mut self.data
+    }
 }
 
 struct NRom {
@@ -333,7 +745,7 @@ impl Mapper for NRom {
 
     fn on_scanline(&mut self) {}
 
-    fn cpu_read(&self, addr: cpu6502::Address) -> MapperReadResult {
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
         if addr.0 >= 0x8000 {
             MapperReadResult::Address(Some((addr.0 & self.mask) as usize))
         } else {
@@ -341,7 +753,7 @@ impl Mapper for NRom {
         }
     }
 
-    fn ppu_read(&self, addr: ppu2C02::Address) -> MapperReadResult {
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
         if addr <= 0x1FFF {
             MapperReadResult::Address(Some(addr.0 .0 as usize))
         } else {
@@ -366,7 +778,7 @@ struct Mmc1 {
     chr_bank_4_lo: u8,
     chr_bank_4_hi: u8,
     mirror: MirrorMode,
-    prg_ram: Box<[Wrapping<u8>]>,
+    prg_ram: PrgRam,
 }
 impl Mmc1 {
     fn new(prg_banks: u8) -> Self {
@@ -382,7 +794,7 @@ impl Mmc1 {
             chr_bank_4_lo: 0,
             chr_bank_4_hi: 0,
             mirror: MirrorMode::Horizontal,
-            prg_ram: vec![Wrapping(0); 0x2000].into_boxed_slice(),
+            prg_ram: PrgRam::new(PrgRam::DEFAULT_SIZE),
         }
     }
 }
@@ -399,9 +811,9 @@ impl Mapper for Mmc1 {
 
     fn on_scanline(&mut self) {}
 
-    fn cpu_read(&self, addr: cpu6502::Address) -> MapperReadResult {
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
         if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
-            MapperReadResult::Data(self.prg_ram[(addr.0 & 0x1FFF) as usize])
+            self.prg_ram.read((addr.0 & 0x1FFF) as usize)
         } else if addr.0 >= 0x8000 {
             if (self.control & 0x08) != 0 {
                 // 16k mode
@@ -427,7 +839,7 @@ impl Mapper for Mmc1 {
         }
     }
 
-    fn ppu_read(&self, addr: ppu2C02::Address) -> MapperReadResult {
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
         if addr <= 0x1FFF {
             if (self.control & 0x10) != 0 {
                 // 4k mode
@@ -453,7 +865,7 @@ impl Mapper for Mmc1 {
 
     fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
         if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
-            self.prg_ram[(addr.0 & 0x1FFF) as usize] = data;
+            self.prg_ram.write((addr.0 & 0x1FFF) as usize, data);
         } else if addr.0 >= 0x8000 {
             if (data.0 & 0x80) != 0 {
                 self.load = 0;
@@ -528,17 +940,33 @@ impl Mapper for Mmc1 {
         self.chr_bank_4_lo = 0;
         self.chr_bank_4_hi = 0;
     }
+
+    #[inline]
+    fn prg_ram(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+/// Submapper 1 of the discrete boards has no bus conflicts, submapper 2 has AND-type conflicts
+fn bus_conflicts_from_submapper(submapper: u8, default: bool) -> bool {
+    match submapper {
+        1 => false,
+        2 => true,
+        _ => default,
+    }
 }
 
 struct UxRom {
     prg_bank_lo: u8,
     prg_bank_hi: u8,
+    bus_conflicts: bool,
 }
 impl UxRom {
-    fn new(prg_banks: u8) -> Self {
+    fn new(submapper: u8, prg_banks: u8) -> Self {
         Self {
             prg_bank_lo: 0,
             prg_bank_hi: prg_banks - 1,
+            bus_conflicts: bus_conflicts_from_submapper(submapper, true),
         }
     }
 }
@@ -547,6 +975,10 @@ impl Mapper for UxRom {
         None
     }
 
+    fn has_bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
     fn interrupt_state(&self) -> bool {
         false
     }
@@ -555,7 +987,7 @@ impl Mapper for UxRom {
 
     fn on_scanline(&mut self) {}
 
-    fn cpu_read(&self, addr: cpu6502::Address) -> MapperReadResult {
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
         if (addr.0 >= 0x8000) && (addr.0 <= 0xBFFF) {
             MapperReadResult::Address(Some(
                 (self.prg_bank_lo as usize) * PRG_BANK_SIZE + ((addr.0 & 0x3FFF) as usize),
@@ -569,7 +1001,7 @@ impl Mapper for UxRom {
         }
     }
 
-    fn ppu_read(&self, addr: ppu2C02::Address) -> MapperReadResult {
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
         if addr <= 0x1FFF {
             MapperReadResult::Address(Some(addr.0 .0 as usize))
         } else {
@@ -591,12 +1023,14 @@ impl Mapper for UxRom {
 struct CNRom {
     mask: u16,
     chr_bank: u8,
+    bus_conflicts: bool,
 }
 impl CNRom {
-    fn new(prg_banks: u8) -> Self {
+    fn new(submapper: u8, prg_banks: u8) -> Self {
         Self {
             mask: if prg_banks > 1 { 0x7FFF } else { 0x3FFF },
             chr_bank: 0,
+            bus_conflicts: bus_conflicts_from_submapper(submapper, true),
         }
     }
 }
@@ -605,6 +1039,10 @@ impl Mapper for CNRom {
         None
     }
 
+    fn has_bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
     fn interrupt_state(&self) -> bool {
         false
     }
@@ -613,7 +1051,7 @@ impl Mapper for CNRom {
 
     fn on_scanline(&mut self) {}
 
-    fn cpu_read(&self, addr: cpu6502::Address) -> MapperReadResult {
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
         if addr.0 >= 0x8000 {
             MapperReadResult::Address(Some((addr.0 & self.mask) as usize))
         } else {
@@ -621,7 +1059,7 @@ impl Mapper for CNRom {
         }
     }
 
-    fn ppu_read(&self, addr: ppu2C02::Address) -> MapperReadResult {
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
         if addr <= 0x1FFF {
             MapperReadResult::Address(Some(
                 (self.chr_bank as usize) * CHR_BANK_SIZE + (addr.0 .0 as usize),
@@ -642,24 +1080,46 @@ impl Mapper for CNRom {
     }
 }
 
+/// Boards built around the MMC3 banking logic
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Mmc3Board {
+    Mmc3,
+    /// Nametables are selected by bit 7 of the CHR bank registers
+    TxSRom,
+    /// CHR banks with bit 6 set select 8k of CHR RAM instead of CHR ROM
+    TqRom,
+    /// Predecessor of the MMC3 without IRQ, PRG RAM, mirroring control and banking modes
+    Namco108,
+}
+
 struct Mmc3 {
+    board: Mmc3Board,
     target_reg: usize,
     register: [usize; 8],
     prg_bank: [usize; 4],
     chr_bank: [usize; 8],
     interrupt_counter: u16,
     interrupt_step: u16,
+    interrupt_reload: bool,
     interrupt_active: bool,
     interrupt_enabled: bool,
+    // The older Rev A chips don't trigger an IRQ when the counter is reloaded with zero
+    rev_a: bool,
+    a12_high: bool,
+    a12_low_since: u64,
     prg_bank_mode: bool,
     chr_inversion: bool,
     prg_banks: u8,
     mirror: MirrorMode,
-    prg_ram: Box<[Wrapping<u8>]>,
+    prg_ram: PrgRam,
 }
 impl Mmc3 {
-    fn new(prg_banks: u8) -> Self {
+    // A12 has to stay low for about three CPU cycles before a rising edge clocks the counter
+    const A12_FILTER_CYCLES: u64 = 10;
+
+    fn new(prg_banks: u8, rev_a: bool) -> Self {
         Self {
+            board: Mmc3Board::Mmc3,
             target_reg: 0,
             register: [0; 8],
             prg_bank: [
@@ -671,44 +1131,83 @@ impl Mmc3 {
             chr_bank: [0; 8],
             interrupt_counter: 0,
             interrupt_step: 0,
+            interrupt_reload: false,
             interrupt_active: false,
             interrupt_enabled: false,
+            rev_a,
+            a12_high: false,
+            a12_low_since: 0,
             prg_bank_mode: false,
             chr_inversion: false,
             prg_banks,
             mirror: MirrorMode::Horizontal,
-            prg_ram: vec![Wrapping(0); 0x2000].into_boxed_slice(),
+            prg_ram: PrgRam::new(PrgRam::DEFAULT_SIZE),
         }
     }
-}
-impl Mapper for Mmc3 {
-    fn mirror(&self) -> Option<MirrorMode> {
-        Some(self.mirror)
-    }
 
-    fn interrupt_state(&self) -> bool {
-        self.interrupt_active
+    fn with_board(prg_banks: u8, board: Mmc3Board) -> Self {
+        Self {
+            board,
+            ..Self::new(prg_banks, false)
+        }
     }
 
-    fn reset_interrupt(&mut self) {
-        self.interrupt_active = false;
+    #[inline]
+    fn chr_bank_number(&self, slot: usize) -> usize {
+        self.chr_bank[slot] / 0x0400
     }
 
-    fn on_scanline(&mut self) {
-        if self.interrupt_counter == 0 {
+    fn clock_counter(&mut self) {
+        let previous_counter = self.interrupt_counter;
+        let reload = self.interrupt_reload;
+        if (self.interrupt_counter == 0) || self.interrupt_reload {
             self.interrupt_counter = self.interrupt_step;
+            self.interrupt_reload = false;
         } else {
             self.interrupt_counter -= 1;
         }
 
-        if (self.interrupt_counter == 0) && self.interrupt_enabled {
+        let trigger = if self.rev_a {
+            (previous_counter != 0) || reload
+        } else {
+            true
+        };
+        if (self.interrupt_counter == 0) && trigger && self.interrupt_enabled {
             self.interrupt_active = true;
         }
     }
+}
+impl Mapper for Mmc3 {
+    fn mirror(&self) -> Option<MirrorMode> {
+        if self.board == Mmc3Board::Namco108 {
+            None
+        } else {
+            Some(self.mirror)
+        }
+    }
 
-    fn cpu_read(&self, addr: cpu6502::Address) -> MapperReadResult {
-        if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
-            MapperReadResult::Data(self.prg_ram[(addr.0 & 0x1FFF) as usize])
+    fn nametable(&self, index: usize) -> Option<Nametable> {
+        if self.board == Mmc3Board::TxSRom {
+            // The CHR bank registers of the lower pattern table double as nametable selects
+            Some(Nametable::Ciram((self.chr_bank_number(index) >> 7) & 0x01))
+        } else {
+            None
+        }
+    }
+
+    fn interrupt_state(&self) -> bool {
+        self.interrupt_active
+    }
+
+    fn reset_interrupt(&mut self) {
+        self.interrupt_active = false;
+    }
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) && (self.board != Mmc3Board::Namco108) {
+            self.prg_ram.read((addr.0 & 0x1FFF) as usize)
         } else if addr.0 >= 0x8000 {
             let bank = ((addr.0 >> 13) & 0x03) as usize;
             let mapped_addr = self.prg_bank[bank] + ((addr.0 & 0x1FFF) as usize);
@@ -718,7 +1217,7 @@ impl Mapper for Mmc3 {
         }
     }
 
-    fn ppu_read(&self, addr: ppu2C02::Address) -> MapperReadResult {
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
         if addr <= 0x1FFF {
             let bank = ((addr >> 10u32) & 0x07).0 .0 as usize;
             let mapped_addr = self.chr_bank[bank] + ((addr & 0x03FF).0 .0 as usize);
@@ -731,9 +1230,25 @@ impl Mapper for Mmc3 {
     fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
         const PRG_BANK_SIZE_L: usize = 0x2000;
         const CHR_BANK_SIZE_L: usize = 0x0400;
+        // Register widths of the Namco 108
+        const NAMCO_108_MASKS: [u8; 8] = [0x3E, 0x3E, 0x3F, 0x3F, 0x3F, 0x3F, 0x0F, 0x0F];
+
+        let data = if self.board == Mmc3Board::Namco108 {
+            if (addr.0 < 0x8000) || (addr.0 > 0x9FFF) {
+                return;
+            }
+
+            if (addr.0 & 0x0001) == 0 {
+                data & Wrapping(0x07)
+            } else {
+                data & Wrapping(NAMCO_108_MASKS[self.target_reg])
+            }
+        } else {
+            data
+        };
 
         if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
-            self.prg_ram[(addr.0 & 0x1FFF) as usize] = data;
+            self.prg_ram.write((addr.0 & 0x1FFF) as usize, data);
         } else if addr.0 >= 0x8000 {
             if addr.0 <= 0x9FFF {
                 // Bank select
@@ -789,6 +1304,7 @@ impl Mapper for Mmc3 {
                     self.interrupt_step = data.0 as u16;
                 } else {
                     self.interrupt_counter = 0;
+                    self.interrupt_reload = true;
                 }
             } else {
                 // Interrupts
@@ -812,6 +1328,9 @@ impl Mapper for Mmc3 {
         self.interrupt_enabled = false;
         self.interrupt_counter = 0;
         self.interrupt_step = 0;
+        self.interrupt_reload = false;
+        self.a12_high = false;
+        self.a12_low_since = 0;
 
         self.register = [0; 8];
         self.chr_bank = [0; 8];
@@ -822,17 +1341,67 @@ impl Mapper for Mmc3 {
             ((self.prg_banks as usize) * 2 - 1) * 0x2000,
         ];
     }
+
+    #[inline]
+    fn prg_ram(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn on_ppu_address(&mut self, addr: ppu2C02::Address, ppu_cycle: u64) {
+        if self.board == Mmc3Board::Namco108 {
+            return;
+        }
+
+        let a12_high = (addr & 0x1000).0 .0 != 0;
+        if a12_high && !self.a12_high {
+            // Short pulses on A12, like the ones caused by mixing pattern tables
+            // for 8x16 sprites, are filtered out by the chip
+            if ppu_cycle.wrapping_sub(self.a12_low_since) >= Self::A12_FILTER_CYCLES {
+                self.clock_counter();
+            }
+        } else if !a12_high && self.a12_high {
+            self.a12_low_since = ppu_cycle;
+        }
+        self.a12_high = a12_high;
+    }
+
+    fn chr_ram_address(&self, addr: ppu2C02::Address) -> Option<usize> {
+        const CHR_BANK_SIZE_L: usize = 0x0400;
+
+        if self.board == Mmc3Board::TqRom {
+            let bank = self.chr_bank_number(((addr >> 10u32) & 0x07).0 .0 as usize);
+            if (bank & 0x40) != 0 {
+                Some((bank & 0x07) * CHR_BANK_SIZE_L + ((addr & 0x03FF).0 .0 as usize))
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    }
+
+    fn default_chr_ram_size(&self, chr_rom_size: usize) -> usize {
+        // TQROM has 8k of CHR RAM next to its CHR ROM
+        if (chr_rom_size == 0) || (self.board == Mmc3Board::TqRom) {
+            CHR_BANK_SIZE
+        } else {
+            0
+        }
+    }
 }
 
 struct AxRom {
     prg_bank: u8,
     mirror: MirrorMode,
+    bus_conflicts: bool,
 }
 impl AxRom {
-    fn new() -> Self {
+    fn new(submapper: u8) -> Self {
         Self {
             prg_bank: 0,
             mirror: MirrorMode::OneScreenLow,
+            // Only AMROM has bus conflicts and some ANROM/AOROM games rely on their absence
+            bus_conflicts: bus_conflicts_from_submapper(submapper, false),
         }
     }
 }
@@ -841,6 +1410,10 @@ impl Mapper for AxRom {
         Some(self.mirror)
     }
 
+    fn has_bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
     fn interrupt_state(&self) -> bool {
         false
     }
@@ -849,7 +1422,7 @@ impl Mapper for AxRom {
 
     fn on_scanline(&mut self) {}
 
-    fn cpu_read(&self, addr: cpu6502::Address) -> MapperReadResult {
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
         if addr.0 >= 0x8000 {
             MapperReadResult::Address(Some(
                 (self.prg_bank as usize) * 2 * PRG_BANK_SIZE + ((addr.0 & 0x7FFF) as usize),
@@ -859,7 +1432,7 @@ impl Mapper for AxRom {
         }
     }
 
-    fn ppu_read(&self, addr: ppu2C02::Address) -> MapperReadResult {
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
         if addr <= 0x1FFF {
             MapperReadResult::Address(Some(addr.0 .0 as usize))
         } else {
@@ -901,7 +1474,322 @@ impl Mapper for GxRom {
         None
     }
 
-    fn interrupt_state(&self) -> bool {
+    fn has_bus_conflicts(&self) -> bool {
+        true
+    }
+
+    fn interrupt_state(&self) -> bool {
+        false
+    }
+
+    fn reset_interrupt(&mut self) {}
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        if addr.0 >= 0x8000 {
+            MapperReadResult::Address(Some(
+                (self.prg_bank as usize) * 2 * PRG_BANK_SIZE + ((addr.0 & 0x7FFF) as usize),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        if addr <= 0x1FFF {
+            MapperReadResult::Address(Some(
+                (self.chr_bank as usize) * CHR_BANK_SIZE + (addr.0 .0 as usize),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        if addr.0 >= 0x8000 {
+            self.chr_bank = data.0 & 0x03;
+            self.prg_bank = (data.0 >> 4) & 0x03;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+        self.chr_bank = 0;
+    }
+}
+
+/// Color Dreams
+struct ColorDreams {
+    prg_banks: u8,
+    chr_banks: u8,
+    prg_bank: u8,
+    chr_bank: u8,
+}
+impl ColorDreams {
+    fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+}
+impl Mapper for ColorDreams {
+    fn mirror(&self) -> Option<MirrorMode> {
+        None
+    }
+
+    fn interrupt_state(&self) -> bool {
+        false
+    }
+
+    fn reset_interrupt(&mut self) {}
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        if addr.0 >= 0x8000 {
+            let mapped_addr =
+                (self.prg_bank as usize) * 2 * PRG_BANK_SIZE + ((addr.0 & 0x7FFF) as usize);
+            MapperReadResult::Address(Some(
+                mapped_addr % ((self.prg_banks as usize) * PRG_BANK_SIZE),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        if addr <= 0x1FFF {
+            // Boards with CHR RAM report 0 CHR banks but still have 8k
+            let mapped_addr = (self.chr_bank as usize) * CHR_BANK_SIZE + (addr.0 .0 as usize);
+            MapperReadResult::Address(Some(
+                mapped_addr % ((self.chr_banks.max(1) as usize) * CHR_BANK_SIZE),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        if addr.0 >= 0x8000 {
+            self.prg_bank = data.0 & 0x03;
+            self.chr_bank = data.0 >> 4;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+        self.chr_bank = 0;
+    }
+}
+
+/// Mapper 34 covers two unrelated boards: BNROM with a single PRG register
+/// and NINA-001 with PRG RAM and two 4k CHR banks
+struct BnRom {
+    is_nina: bool,
+    prg_banks: u8,
+    prg_bank: u8,
+    chr_bank: [u8; 2],
+    prg_ram: PrgRam,
+}
+impl BnRom {
+    fn new(submapper: u8, prg_banks: u8, chr_banks: u8) -> Self {
+        // Without a submapper the boards are told apart by their CHR memory,
+        // BNROM only uses 8k of CHR RAM
+        let is_nina = match submapper {
+            1 => true,
+            2 => false,
+            _ => chr_banks > 1,
+        };
+
+        Self {
+            is_nina,
+            prg_banks,
+            prg_bank: 0,
+            chr_bank: [0, 1],
+            prg_ram: PrgRam::new(PrgRam::DEFAULT_SIZE),
+        }
+    }
+}
+impl Mapper for BnRom {
+    fn mirror(&self) -> Option<MirrorMode> {
+        None
+    }
+
+    fn interrupt_state(&self) -> bool {
+        false
+    }
+
+    fn reset_interrupt(&mut self) {}
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        if self.is_nina && (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
+            self.prg_ram.read((addr.0 & 0x1FFF) as usize)
+        } else if addr.0 >= 0x8000 {
+            let mapped_addr =
+                (self.prg_bank as usize) * 2 * PRG_BANK_SIZE + ((addr.0 & 0x7FFF) as usize);
+            MapperReadResult::Address(Some(
+                mapped_addr % ((self.prg_banks as usize) * PRG_BANK_SIZE),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        const CHR_BANK_SIZE_S: usize = 0x1000;
+
+        if addr <= 0x1FFF {
+            if self.is_nina {
+                let bank = ((addr >> 12u32) & 0x01).0 .0 as usize;
+                MapperReadResult::Address(Some(
+                    (self.chr_bank[bank] as usize) * CHR_BANK_SIZE_S
+                        + ((addr & 0x0FFF).0 .0 as usize),
+                ))
+            } else {
+                MapperReadResult::Address(Some(addr.0 .0 as usize))
+            }
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        if self.is_nina {
+            match addr.0 {
+                0x7FFD => self.prg_bank = data.0 & 0x01,
+                0x7FFE => self.chr_bank[0] = data.0 & 0x0F,
+                0x7FFF => self.chr_bank[1] = data.0 & 0x0F,
+                _ => {}
+            }
+
+            // The registers overlap PRG RAM, so writes to them are stored as well
+            if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
+                self.prg_ram.write((addr.0 & 0x1FFF) as usize, data);
+            }
+        } else if addr.0 >= 0x8000 {
+            self.prg_bank = data.0;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+        self.chr_bank = [0, 1];
+    }
+
+    fn prg_ram(&mut self) -> Option<&mut PrgRam> {
+        if self.is_nina {
+            Some(&mut self.prg_ram)
+        } else {
+            None
+        }
+    }
+}
+
+/// Camerica/Codemasters
+///
+/// Like UxROM but with the bank register at $C000. Fire Hawk (submapper 1)
+/// additionally controls one-screen mirroring through $8000.
+struct Camerica {
+    prg_banks: u8,
+    prg_bank: u8,
+    mirror: Option<MirrorMode>,
+    has_mirror_control: bool,
+}
+impl Camerica {
+    fn new(submapper: u8, prg_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            prg_bank: 0,
+            mirror: None,
+            has_mirror_control: submapper == 1,
+        }
+    }
+}
+impl Mapper for Camerica {
+    fn mirror(&self) -> Option<MirrorMode> {
+        self.mirror
+    }
+
+    fn interrupt_state(&self) -> bool {
+        false
+    }
+
+    fn reset_interrupt(&mut self) {}
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        if (addr.0 >= 0x8000) && (addr.0 <= 0xBFFF) {
+            MapperReadResult::Address(Some(
+                ((self.prg_bank % self.prg_banks) as usize) * PRG_BANK_SIZE
+                    + ((addr.0 & 0x3FFF) as usize),
+            ))
+        } else if addr.0 >= 0xC000 {
+            MapperReadResult::Address(Some(
+                ((self.prg_banks - 1) as usize) * PRG_BANK_SIZE + ((addr.0 & 0x3FFF) as usize),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        if addr <= 0x1FFF {
+            MapperReadResult::Address(Some(addr.0 .0 as usize))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        if addr.0 >= 0xC000 {
+            self.prg_bank = data.0 & 0x0F;
+        } else if self.has_mirror_control && (addr.0 >= 0x8000) && (addr.0 <= 0x9FFF) {
+            self.mirror = if (data.0 & 0x10) != 0 {
+                Some(MirrorMode::OneScreenHigh)
+            } else {
+                Some(MirrorMode::OneScreenLow)
+            };
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+        self.mirror = None;
+    }
+}
+
+/// AVE NINA-003/NINA-006
+///
+/// The register is located in the expansion area and decoded from $4100 with mask $E100.
+struct Nina003 {
+    prg_banks: u8,
+    chr_banks: u8,
+    prg_bank: u8,
+    chr_bank: u8,
+}
+impl Nina003 {
+    fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+}
+impl Mapper for Nina003 {
+    fn mirror(&self) -> Option<MirrorMode> {
+        None
+    }
+
+    fn interrupt_state(&self) -> bool {
         false
     }
 
@@ -909,50 +1797,2049 @@ impl Mapper for GxRom {
 
     fn on_scanline(&mut self) {}
 
-    fn cpu_read(&self, addr: cpu6502::Address) -> MapperReadResult {
-        if addr.0 >= 0x8000 {
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        if addr.0 >= 0x8000 {
+            let mapped_addr =
+                (self.prg_bank as usize) * 2 * PRG_BANK_SIZE + ((addr.0 & 0x7FFF) as usize);
+            MapperReadResult::Address(Some(
+                mapped_addr % ((self.prg_banks as usize) * PRG_BANK_SIZE),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        if addr <= 0x1FFF {
+            let mapped_addr = (self.chr_bank as usize) * CHR_BANK_SIZE + (addr.0 .0 as usize);
+            MapperReadResult::Address(Some(
+                mapped_addr % ((self.chr_banks.max(1) as usize) * CHR_BANK_SIZE),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        if (addr.0 & 0xE100) == 0x4100 {
+            self.prg_bank = (data.0 >> 3) & 0x01;
+            self.chr_bank = data.0 & 0x07;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+        self.chr_bank = 0;
+    }
+}
+
+/// Jaleco JF-11/JF-14, like GxROM but with the register at $6000
+struct Jaleco {
+    prg_banks: u8,
+    chr_banks: u8,
+    prg_bank: u8,
+    chr_bank: u8,
+}
+impl Jaleco {
+    fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+}
+impl Mapper for Jaleco {
+    fn mirror(&self) -> Option<MirrorMode> {
+        None
+    }
+
+    fn interrupt_state(&self) -> bool {
+        false
+    }
+
+    fn reset_interrupt(&mut self) {}
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        if addr.0 >= 0x8000 {
+            let mapped_addr =
+                (self.prg_bank as usize) * 2 * PRG_BANK_SIZE + ((addr.0 & 0x7FFF) as usize);
+            MapperReadResult::Address(Some(
+                mapped_addr % ((self.prg_banks as usize) * PRG_BANK_SIZE),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        if addr <= 0x1FFF {
+            let mapped_addr = (self.chr_bank as usize) * CHR_BANK_SIZE + (addr.0 .0 as usize);
+            MapperReadResult::Address(Some(
+                mapped_addr % ((self.chr_banks.max(1) as usize) * CHR_BANK_SIZE),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
+            self.prg_bank = (data.0 >> 4) & 0x03;
+            self.chr_bank = data.0 & 0x0F;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+        self.chr_bank = 0;
+    }
+}
+
+/// UNROM with the first bank fixed and the upper bank switchable, used by Crazy Climber
+struct UnRomReversed {
+    prg_banks: u8,
+    prg_bank: u8,
+}
+impl UnRomReversed {
+    fn new(prg_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            prg_bank: 0,
+        }
+    }
+}
+impl Mapper for UnRomReversed {
+    fn mirror(&self) -> Option<MirrorMode> {
+        None
+    }
+
+    fn interrupt_state(&self) -> bool {
+        false
+    }
+
+    fn reset_interrupt(&mut self) {}
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        if (addr.0 >= 0x8000) && (addr.0 <= 0xBFFF) {
+            MapperReadResult::Address(Some((addr.0 & 0x3FFF) as usize))
+        } else if addr.0 >= 0xC000 {
+            MapperReadResult::Address(Some(
+                ((self.prg_bank % self.prg_banks) as usize) * PRG_BANK_SIZE
+                    + ((addr.0 & 0x3FFF) as usize),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        if addr <= 0x1FFF {
+            MapperReadResult::Address(Some(addr.0 .0 as usize))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        if addr.0 >= 0x8000 {
+            self.prg_bank = data.0 & 0x0F;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum ChrLatch {
+    FD,
+    FE,
+}
+
+// MMC4 only differs from MMC2 in PRG banking, PRG RAM and the way the latches are triggered
+struct Mmc2 {
+    is_mmc4: bool,
+    prg_banks: u8,
+    prg_bank: u8,
+    chr_bank_lo: [u8; 2],
+    chr_bank_hi: [u8; 2],
+    latch_lo: ChrLatch,
+    latch_hi: ChrLatch,
+    mirror: MirrorMode,
+    prg_ram: PrgRam,
+}
+impl Mmc2 {
+    fn new(prg_banks: u8, is_mmc4: bool) -> Self {
+        Self {
+            is_mmc4,
+            prg_banks,
+            prg_bank: 0,
+            chr_bank_lo: [0; 2],
+            chr_bank_hi: [0; 2],
+            latch_lo: ChrLatch::FE,
+            latch_hi: ChrLatch::FE,
+            mirror: MirrorMode::Vertical,
+            prg_ram: PrgRam::new(PrgRam::DEFAULT_SIZE),
+        }
+    }
+
+    #[inline]
+    const fn latch_index(latch: ChrLatch) -> usize {
+        match latch {
+            ChrLatch::FD => 0,
+            ChrLatch::FE => 1,
+        }
+    }
+
+    fn update_latches(&mut self, addr: u16) {
+        // The latches are updated after the fetch, so the tile that triggered them
+        // is still read from the old bank
+        if addr <= 0x0FFF {
+            let (fd_match, fe_match) = if self.is_mmc4 {
+                ((addr & 0xFFF8) == 0x0FD8, (addr & 0xFFF8) == 0x0FE8)
+            } else {
+                (addr == 0x0FD8, addr == 0x0FE8)
+            };
+
+            if fd_match {
+                self.latch_lo = ChrLatch::FD;
+            } else if fe_match {
+                self.latch_lo = ChrLatch::FE;
+            }
+        } else {
+            if (addr & 0xFFF8) == 0x1FD8 {
+                self.latch_hi = ChrLatch::FD;
+            } else if (addr & 0xFFF8) == 0x1FE8 {
+                self.latch_hi = ChrLatch::FE;
+            }
+        }
+    }
+}
+impl Mapper for Mmc2 {
+    fn mirror(&self) -> Option<MirrorMode> {
+        Some(self.mirror)
+    }
+
+    fn interrupt_state(&self) -> bool {
+        false
+    }
+
+    fn reset_interrupt(&mut self) {}
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        const PRG_BANK_SIZE_S: usize = 0x2000;
+
+        if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
+            self.prg_ram.read((addr.0 & 0x1FFF) as usize)
+        } else if addr.0 >= 0x8000 {
+            if self.is_mmc4 {
+                // 16k switchable bank followed by the last 16k bank
+                let bank = if addr.0 <= 0xBFFF {
+                    self.prg_bank as usize
+                } else {
+                    (self.prg_banks as usize) - 1
+                };
+                MapperReadResult::Address(Some(bank * PRG_BANK_SIZE + ((addr.0 & 0x3FFF) as usize)))
+            } else {
+                // 8k switchable bank followed by the last three 8k banks
+                let bank = if addr.0 <= 0x9FFF {
+                    self.prg_bank as usize
+                } else {
+                    let last_banks = (self.prg_banks as usize) * 2 - 4;
+                    last_banks + (((addr.0 - 0x8000) >> 13) as usize)
+                };
+                MapperReadResult::Address(Some(
+                    bank * PRG_BANK_SIZE_S + ((addr.0 & 0x1FFF) as usize),
+                ))
+            }
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        const CHR_BANK_SIZE_S: usize = 0x1000;
+
+        if addr <= 0x1FFF {
+            let bank = if addr <= 0x0FFF {
+                self.chr_bank_lo[Self::latch_index(self.latch_lo)]
+            } else {
+                self.chr_bank_hi[Self::latch_index(self.latch_hi)]
+            };
+            let mapped_addr = (bank as usize) * CHR_BANK_SIZE_S + ((addr & 0x0FFF).0 .0 as usize);
+
+            self.update_latches(addr.0 .0);
+            MapperReadResult::Address(Some(mapped_addr))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
+            self.prg_ram.write((addr.0 & 0x1FFF) as usize, data);
+        } else if addr.0 >= 0xA000 {
+            match addr.0 & 0xF000 {
+                0xA000 => self.prg_bank = data.0 & 0x0F,
+                0xB000 => self.chr_bank_lo[0] = data.0 & 0x1F,
+                0xC000 => self.chr_bank_lo[1] = data.0 & 0x1F,
+                0xD000 => self.chr_bank_hi[0] = data.0 & 0x1F,
+                0xE000 => self.chr_bank_hi[1] = data.0 & 0x1F,
+                0xF000 => {
+                    self.mirror = if (data.0 & 0x01) == 0 {
+                        MirrorMode::Vertical
+                    } else {
+                        MirrorMode::Horizontal
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+        self.chr_bank_lo = [0; 2];
+        self.chr_bank_hi = [0; 2];
+        self.latch_lo = ChrLatch::FE;
+        self.latch_hi = ChrLatch::FE;
+        self.mirror = MirrorMode::Vertical;
+    }
+
+    #[inline]
+    fn prg_ram(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+struct Mmc5 {
+    prg_banks: u8,
+    chr_size: usize,
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: [u8; 2],
+    exram_mode: u8,
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attr: u8,
+    prg_ram_bank: u8,
+    prg_bank: [u8; 4],
+    chr_bank: [usize; 12],
+    chr_upper: usize,
+    last_chr_set_b: bool,
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+    split_y: u8,
+    in_split: bool,
+    irq_compare: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    irq_line: bool,
+    in_frame: bool,
+    scanline: u8,
+    multiplicand: u8,
+    multiplier: u8,
+    large_sprites: bool,
+    rendering: bool,
+    fetch: PpuFetch,
+    tile_index: u8,
+    ex_attr: u8,
+    prg_ram: PrgRam,
+    exram: Box<[Wrapping<u8>]>,
+    audio: Mmc5Audio,
+}
+impl Mmc5 {
+    const PRG_BANK_SIZE_S: usize = 0x2000;
+    const PRG_RAM_SIZE: usize = 0x10000;
+
+    fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_size: (chr_banks.max(1) as usize) * CHR_BANK_SIZE,
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attr: 0,
+            prg_ram_bank: 0,
+            prg_bank: [0xFF; 4],
+            chr_bank: [0; 12],
+            chr_upper: 0,
+            last_chr_set_b: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            split_y: 0,
+            in_split: false,
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            irq_line: false,
+            in_frame: false,
+            scanline: 0,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            large_sprites: false,
+            rendering: false,
+            fetch: PpuFetch::Background,
+            tile_index: 0,
+            ex_attr: 0,
+            prg_ram: PrgRam::new(Self::PRG_RAM_SIZE),
+            exram: vec![Wrapping(0); 0x0400].into_boxed_slice(),
+            audio: Mmc5Audio::new(),
+        }
+    }
+
+    #[inline]
+    fn prg_ram_writable(&self) -> bool {
+        (self.prg_ram_protect[0] == 0x02) && (self.prg_ram_protect[1] == 0x01)
+    }
+
+    #[inline]
+    fn prg_ram_address(bank: u8, addr: u16) -> usize {
+        ((bank & 0x07) as usize) * Self::PRG_BANK_SIZE_S + ((addr & 0x1FFF) as usize)
+    }
+
+    /// Returns the bank register value selected for an address in $8000-$FFFF,
+    /// with the bank number adjusted to 8k units. Bit 7 selects ROM when set.
+    fn prg_bank_for(&self, addr: u16) -> u8 {
+        let slot = ((addr - 0x8000) >> 13) as u8;
+        // $5117 always selects ROM
+        let last = self.prg_bank[3] | 0x80;
+
+        match self.prg_mode {
+            0 => (last & 0xFC) | slot,
+            1 => {
+                if slot < 2 {
+                    (self.prg_bank[1] & 0xFE) | slot
+                } else {
+                    (last & 0xFE) | (slot - 2)
+                }
+            }
+            2 => match slot {
+                0 | 1 => (self.prg_bank[1] & 0xFE) | slot,
+                2 => self.prg_bank[2],
+                _ => last,
+            },
+            _ => match slot {
+                3 => last,
+                _ => self.prg_bank[slot as usize],
+            },
+        }
+    }
+
+    fn chr_address(&self, addr: u16, set_b: bool) -> usize {
+        let (bank, size) = if set_b {
+            // The background set only covers 4k and is mirrored to both pattern tables
+            match self.chr_mode {
+                0 => (self.chr_bank[11], 0x2000),
+                1 => (self.chr_bank[11], 0x1000),
+                2 => (
+                    self.chr_bank[9 + ((addr >> 11) & 0x01) as usize * 2],
+                    0x0800,
+                ),
+                _ => (self.chr_bank[8 + ((addr >> 10) & 0x03) as usize], 0x0400),
+            }
+        } else {
+            match self.chr_mode {
+                0 => (self.chr_bank[7], 0x2000),
+                1 => (
+                    self.chr_bank[3 + ((addr >> 12) & 0x01) as usize * 4],
+                    0x1000,
+                ),
+                2 => (
+                    self.chr_bank[1 + ((addr >> 11) & 0x03) as usize * 2],
+                    0x0800,
+                ),
+                _ => (self.chr_bank[((addr >> 10) & 0x07) as usize], 0x0400),
+            }
+        };
+
+        (bank * size + ((addr as usize) & (size - 1))) % self.chr_size
+    }
+
+    #[inline]
+    fn fetching_background(&self) -> bool {
+        self.in_frame && self.rendering && (self.fetch == PpuFetch::Background)
+    }
+}
+impl Mapper for Mmc5 {
+    fn mirror(&self) -> Option<MirrorMode> {
+        None
+    }
+
+    fn nametable(&self, index: usize) -> Option<Nametable> {
+        match (self.nametable_mapping >> (index * 2)) & 0x03 {
+            0 => Some(Nametable::Ciram(0)),
+            1 => Some(Nametable::Ciram(1)),
+            _ => Some(Nametable::Mapper), // ExRAM or fill mode
+        }
+    }
+
+    fn interrupt_state(&self) -> bool {
+        self.irq_line
+    }
+
+    fn reset_interrupt(&mut self) {
+        // The pending flag stays set until $5204 is read
+        self.irq_line = false;
+    }
+
+    fn on_scanline(&mut self) {
+        if !self.in_frame {
+            self.in_frame = true;
+            self.scanline = 0;
+            self.split_y = self.split_scroll;
+        } else {
+            self.scanline += 1;
+            self.split_y = if self.split_y >= 239 {
+                0
+            } else {
+                self.split_y + 1
+            };
+
+            if (self.irq_compare != 0) && (self.scanline == self.irq_compare) {
+                self.irq_pending = true;
+                if self.irq_enabled {
+                    self.irq_line = true;
+                }
+            }
+
+            if self.scanline >= 240 {
+                self.in_frame = false;
+            }
+        }
+
+        self.tile_index = 0;
+        self.in_split = false;
+    }
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        match addr.0 {
+            0x5015 => MapperReadResult::Data(Wrapping(self.audio.read(addr.0 - 0x5000))),
+            0x5204 => {
+                let mut result: u8 = 0x00;
+                if self.irq_pending {
+                    result |= 0x80;
+                }
+                if self.in_frame {
+                    result |= 0x40;
+                }
+                self.irq_pending = false;
+                self.irq_line = false;
+                MapperReadResult::Data(Wrapping(result))
+            }
+            0x5205 => {
+                let product = (self.multiplicand as u16) * (self.multiplier as u16);
+                MapperReadResult::Data(Wrapping(product as u8))
+            }
+            0x5206 => {
+                let product = (self.multiplicand as u16) * (self.multiplier as u16);
+                MapperReadResult::Data(Wrapping((product >> 8) as u8))
+            }
+            0x5C00..=0x5FFF => {
+                if self.exram_mode >= 2 {
+                    MapperReadResult::Data(self.exram[(addr.0 & 0x03FF) as usize])
+                } else {
+                    MapperReadResult::Address(None)
+                }
+            }
+            0x6000..=0x7FFF => self
+                .prg_ram
+                .read(Self::prg_ram_address(self.prg_ram_bank, addr.0)),
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank_for(addr.0);
+                if (bank & 0x80) != 0 {
+                    let prg_size = (self.prg_banks as usize) * PRG_BANK_SIZE;
+                    let mapped_addr = ((bank & 0x7F) as usize) * Self::PRG_BANK_SIZE_S
+                        + ((addr.0 & 0x1FFF) as usize);
+                    MapperReadResult::Address(Some(mapped_addr % prg_size))
+                } else {
+                    self.prg_ram.read(Self::prg_ram_address(bank, addr.0))
+                }
+            }
+            _ => MapperReadResult::Address(None),
+        }
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        if addr <= 0x1FFF {
+            let addr = addr.0 .0;
+            let mapped_addr = if self.fetching_background() && self.in_split {
+                // The split region has its own 4k bank and vertical scroll
+                let fine_y = (self.split_y & 0x07) as u16;
+                ((self.split_bank as usize) * 0x1000 + ((addr & 0x0FF8) | fine_y) as usize)
+                    % self.chr_size
+            } else if self.fetching_background() && (self.exram_mode == 1) {
+                // Extended attribute mode selects a 4k bank for every tile
+                let bank = ((self.ex_attr & 0x3F) as usize) | (self.chr_upper << 6);
+                (bank * 0x1000 + (addr & 0x0FFF) as usize) % self.chr_size
+            } else {
+                // Separate sets for sprites and background only exist in 8x16 sprite mode
+                let set_b = self.large_sprites
+                    && match self.fetch {
+                        PpuFetch::Background => true,
+                        PpuFetch::Sprite => false,
+                        PpuFetch::Cpu => self.last_chr_set_b,
+                    };
+                self.chr_address(addr, set_b)
+            };
+            MapperReadResult::Address(Some(mapped_addr))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        match addr.0 {
+            0x5000..=0x5015 => self.audio.write(addr.0 - 0x5000, data.0),
+            0x5100 => self.prg_mode = data.0 & 0x03,
+            0x5101 => self.chr_mode = data.0 & 0x03,
+            0x5102 => self.prg_ram_protect[0] = data.0 & 0x03,
+            0x5103 => self.prg_ram_protect[1] = data.0 & 0x03,
+            0x5104 => self.exram_mode = data.0 & 0x03,
+            0x5105 => self.nametable_mapping = data.0,
+            0x5106 => self.fill_tile = data.0,
+            0x5107 => self.fill_attr = data.0 & 0x03,
+            0x5113 => self.prg_ram_bank = data.0 & 0x07,
+            0x5114..=0x5117 => self.prg_bank[(addr.0 - 0x5114) as usize] = data.0,
+            0x5120..=0x512B => {
+                let index = (addr.0 - 0x5120) as usize;
+                self.chr_bank[index] = (data.0 as usize) | (self.chr_upper << 8);
+                self.last_chr_set_b = index >= 8;
+            }
+            0x5130 => self.chr_upper = (data.0 & 0x03) as usize,
+            0x5200 => self.split_control = data.0,
+            0x5201 => self.split_scroll = data.0,
+            0x5202 => self.split_bank = data.0,
+            0x5203 => self.irq_compare = data.0,
+            0x5204 => {
+                self.irq_enabled = (data.0 & 0x80) != 0;
+                self.irq_line = self.irq_enabled && self.irq_pending;
+            }
+            0x5205 => self.multiplicand = data.0,
+            0x5206 => self.multiplier = data.0,
+            0x5C00..=0x5FFF => {
+                let index = (addr.0 & 0x03FF) as usize;
+                match self.exram_mode {
+                    // Nametable modes can only be written while rendering
+                    0 | 1 => {
+                        self.exram[index] = if self.in_frame { data } else { Wrapping(0) };
+                    }
+                    2 => self.exram[index] = data,
+                    _ => {}
+                }
+            }
+            0x6000..=0x7FFF => {
+                if self.prg_ram_writable() {
+                    self.prg_ram
+                        .write(Self::prg_ram_address(self.prg_ram_bank, addr.0), data);
+                }
+            }
+            0x8000..=0xDFFF => {
+                let bank = self.prg_bank_for(addr.0);
+                if ((bank & 0x80) == 0) && self.prg_ram_writable() {
+                    self.prg_ram
+                        .write(Self::prg_ram_address(bank, addr.0), data);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prg_mode = 3;
+        self.chr_mode = 0;
+        self.prg_ram_protect = [0; 2];
+        self.exram_mode = 0;
+        self.prg_bank = [0xFF; 4];
+        self.chr_upper = 0;
+        self.split_control = 0;
+        self.in_split = false;
+        self.irq_enabled = false;
+        self.irq_pending = false;
+        self.irq_line = false;
+        self.in_frame = false;
+        self.audio = Mmc5Audio::new();
+    }
+
+    #[inline]
+    fn prg_ram(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn nametable_read(
+        &mut self,
+        addr: ppu2C02::Address,
+        ciram_data: ppu2C02::Word,
+    ) -> ppu2C02::Word {
+        let offset = (addr.0 .0 & 0x03FF) as usize;
+        let index = ((addr.0 .0 >> 10) & 0x03) as usize;
+        let is_attribute = offset >= 0x03C0;
+
+        if self.fetching_background() {
+            if !is_attribute {
+                let tile = self.tile_index;
+                self.tile_index += 1;
+
+                let split_tile = self.split_control & 0x1F;
+                self.in_split = ((self.split_control & 0x80) != 0)
+                    && (self.exram_mode <= 1)
+                    && if (self.split_control & 0x40) != 0 {
+                        tile >= split_tile
+                    } else {
+                        tile < split_tile
+                    };
+
+                if self.exram_mode == 1 {
+                    self.ex_attr = self.exram[offset].0;
+                }
+
+                if self.in_split {
+                    let row = (self.split_y >> 3) as usize;
+                    let column = (tile & 0x1F) as usize;
+                    return self.exram[row * 32 + column];
+                }
+            } else if self.in_split {
+                let row = (self.split_y >> 3) as usize;
+                let column = (self.tile_index.wrapping_sub(1) & 0x1F) as usize;
+                let attr = self.exram[0x03C0 + (row >> 2) * 8 + (column >> 2)].0;
+                let shift = ((row & 0x02) << 1) | (column & 0x02);
+                // Replicate the palette so it is selected regardless of the PPU's own position
+                return Wrapping(((attr >> shift) & 0x03) * 0x55);
+            } else if self.exram_mode == 1 {
+                return Wrapping(((self.ex_attr >> 6) & 0x03) * 0x55);
+            }
+        }
+
+        match (self.nametable_mapping >> (index * 2)) & 0x03 {
+            2 => {
+                if self.exram_mode <= 1 {
+                    self.exram[offset]
+                } else {
+                    Wrapping(0)
+                }
+            }
+            3 => {
+                if is_attribute {
+                    Wrapping(self.fill_attr * 0x55)
+                } else {
+                    Wrapping(self.fill_tile)
+                }
+            }
+            _ => ciram_data,
+        }
+    }
+
+    fn nametable_write(&mut self, addr: ppu2C02::Address, data: ppu2C02::Word) {
+        let index = ((addr.0 .0 >> 10) & 0x03) as usize;
+        if (((self.nametable_mapping >> (index * 2)) & 0x03) == 2) && (self.exram_mode <= 1) {
+            self.exram[(addr.0 .0 & 0x03FF) as usize] = data;
+        }
+    }
+
+    fn set_ppu_fetch(&mut self, fetch: PpuFetch) {
+        self.fetch = fetch;
+    }
+
+    fn on_ppu_register_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        match addr.0 {
+            0 => self.large_sprites = (data.0 & 0x20) != 0,
+            1 => {
+                self.rendering = (data.0 & 0x18) != 0;
+                if !self.rendering {
+                    self.in_frame = false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.audio.clock();
+        }
+    }
+
+    fn audio_sample(&mut self) -> Sample {
+        self.audio.sample()
+    }
+}
+
+/// IRQ counter shared by most Konami VRC chips
+///
+/// In scanline mode the counter is clocked by a prescaler that approximates
+/// the length of a scanline (113.667 CPU cycles), in cycle mode it is clocked every CPU cycle.
+struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    active: bool,
+}
+impl VrcIrq {
+    const PRESCALER_RELOAD: i16 = 341;
+
+    const fn new() -> Self {
+        Self {
+            latch: 0,
+            counter: 0,
+            prescaler: Self::PRESCALER_RELOAD,
+            enabled: false,
+            enable_after_ack: false,
+            cycle_mode: false,
+            active: false,
+        }
+    }
+
+    #[inline]
+    fn set_latch(&mut self, latch: u8) {
+        self.latch = latch;
+    }
+
+    #[inline]
+    fn set_latch_lo(&mut self, data: u8) {
+        self.latch = (self.latch & 0xF0) | (data & 0x0F);
+    }
+
+    #[inline]
+    fn set_latch_hi(&mut self, data: u8) {
+        self.latch = (self.latch & 0x0F) | ((data & 0x0F) << 4);
+    }
+
+    fn set_control(&mut self, data: u8) {
+        self.enable_after_ack = (data & 0x01) != 0;
+        self.enabled = (data & 0x02) != 0;
+        self.cycle_mode = (data & 0x04) != 0;
+        self.active = false;
+
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = Self::PRESCALER_RELOAD;
+        }
+    }
+
+    #[inline]
+    fn acknowledge(&mut self) {
+        self.active = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.active = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.enabled {
+            if self.cycle_mode {
+                self.clock_counter();
+            } else {
+                self.prescaler -= 3;
+                if self.prescaler <= 0 {
+                    self.prescaler += Self::PRESCALER_RELOAD;
+                    self.clock_counter();
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+struct Vrc6 {
+    prg_banks: u8,
+    swap_address_lines: bool,
+    prg_bank_16: u8,
+    prg_bank_8: u8,
+    chr_bank: [u8; 8],
+    mirror: MirrorMode,
+    prg_ram_enabled: bool,
+    irq: VrcIrq,
+    prg_ram: PrgRam,
+    audio: Vrc6Audio,
+}
+impl Vrc6 {
+    fn new(prg_banks: u8, swap_address_lines: bool) -> Self {
+        Self {
+            prg_banks,
+            swap_address_lines,
+            prg_bank_16: 0,
+            prg_bank_8: 0,
+            chr_bank: [0; 8],
+            mirror: MirrorMode::Vertical,
+            prg_ram_enabled: false,
+            irq: VrcIrq::new(),
+            prg_ram: PrgRam::new(PrgRam::DEFAULT_SIZE),
+            audio: Vrc6Audio::new(),
+        }
+    }
+}
+impl Mapper for Vrc6 {
+    fn mirror(&self) -> Option<MirrorMode> {
+        Some(self.mirror)
+    }
+
+    fn interrupt_state(&self) -> bool {
+        self.irq.active
+    }
+
+    fn reset_interrupt(&mut self) {}
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        const PRG_BANK_SIZE_S: usize = 0x2000;
+
+        if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
+            if self.prg_ram_enabled {
+                self.prg_ram.read((addr.0 & 0x1FFF) as usize)
+            } else {
+                MapperReadResult::Address(None)
+            }
+        } else if addr.0 >= 0x8000 {
+            let mapped_addr = if addr.0 <= 0xBFFF {
+                (self.prg_bank_16 as usize) * PRG_BANK_SIZE + ((addr.0 & 0x3FFF) as usize)
+            } else if addr.0 <= 0xDFFF {
+                (self.prg_bank_8 as usize) * PRG_BANK_SIZE_S + ((addr.0 & 0x1FFF) as usize)
+            } else {
+                ((self.prg_banks as usize) * 2 - 1) * PRG_BANK_SIZE_S + ((addr.0 & 0x1FFF) as usize)
+            };
+            MapperReadResult::Address(Some(
+                mapped_addr % ((self.prg_banks as usize) * PRG_BANK_SIZE),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        const CHR_BANK_SIZE_S: usize = 0x0400;
+
+        if addr <= 0x1FFF {
+            let bank = ((addr >> 10u32) & 0x07).0 .0 as usize;
+            let mapped_addr =
+                (self.chr_bank[bank] as usize) * CHR_BANK_SIZE_S + ((addr & 0x03FF).0 .0 as usize);
+            MapperReadResult::Address(Some(mapped_addr))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
+            if self.prg_ram_enabled {
+                self.prg_ram.write((addr.0 & 0x1FFF) as usize, data);
+            }
+        } else if addr.0 >= 0x8000 {
+            let register = (addr.0 >> 12) as u8;
+            let index = if self.swap_address_lines {
+                ((addr.0 & 0x0001) << 1) | ((addr.0 & 0x0002) >> 1)
+            } else {
+                addr.0 & 0x0003
+            };
+
+            match (register, index) {
+                (0x8, _) => self.prg_bank_16 = data.0 & 0x0F,
+                (0x9, _) | (0xA, _) | (0xB, 0..=2) => self.audio.write(register, index, data.0),
+                (0xB, 3) => {
+                    self.prg_ram_enabled = (data.0 & 0x80) != 0;
+                    self.mirror = match (data.0 >> 2) & 0x03 {
+                        0 => MirrorMode::Vertical,
+                        1 => MirrorMode::Horizontal,
+                        2 => MirrorMode::OneScreenLow,
+                        3 => MirrorMode::OneScreenHigh,
+                        _ => unreachable!(),
+                    };
+                }
+                (0xC, _) => self.prg_bank_8 = data.0 & 0x1F,
+                (0xD, _) => self.chr_bank[index as usize] = data.0,
+                (0xE, _) => self.chr_bank[4 + index as usize] = data.0,
+                (0xF, 0) => self.irq.set_latch(data.0),
+                (0xF, 1) => self.irq.set_control(data.0),
+                (0xF, 2) => self.irq.acknowledge(),
+                _ => {}
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank_16 = 0;
+        self.prg_bank_8 = 0;
+        self.chr_bank = [0; 8];
+        self.mirror = MirrorMode::Vertical;
+        self.prg_ram_enabled = false;
+        self.irq.reset();
+        self.audio = Vrc6Audio::new();
+    }
+
+    #[inline]
+    fn prg_ram(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn clock(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.irq.clock();
+            self.audio.clock();
+        }
+    }
+
+    fn audio_sample(&mut self) -> Sample {
+        self.audio.sample()
+    }
+}
+
+/// Konami VRC4 and its predecessor VRC2, which lacks the IRQ counter and PRG swap mode
+///
+/// The boards connect different CPU address lines to the register select inputs of the chip.
+struct Vrc4 {
+    is_vrc2: bool,
+    // Address bits that select bit 0 and bit 1 of the register index
+    select_lines: (u16, u16),
+    // VRC2a ignores the lowest CHR bank bit
+    chr_shift: u8,
+    prg_banks: u8,
+    prg_bank: [u8; 2],
+    prg_swap: bool,
+    chr_bank: [u16; 8],
+    mirror: MirrorMode,
+    prg_ram_enabled: bool,
+    irq: VrcIrq,
+    prg_ram: PrgRam,
+}
+impl Vrc4 {
+    fn new(is_vrc2: bool, select_lines: (u16, u16), chr_shift: u8, prg_banks: u8) -> Self {
+        Self {
+            is_vrc2,
+            select_lines,
+            chr_shift,
+            prg_banks,
+            prg_bank: [0; 2],
+            prg_swap: false,
+            chr_bank: [0; 8],
+            mirror: MirrorMode::Vertical,
+            prg_ram_enabled: is_vrc2,
+            irq: VrcIrq::new(),
+            prg_ram: PrgRam::new(PrgRam::DEFAULT_SIZE),
+        }
+    }
+
+    fn from_id(id: u8, submapper: u8, prg_banks: u8) -> Self {
+        // Without a submapper the lines of both variants are combined, this works
+        // because games only ever use addresses that are valid on their own board
+        match (id, submapper) {
+            (21, 1) => Self::new(false, (0x02, 0x04), 0, prg_banks), // VRC4a
+            (21, 2) => Self::new(false, (0x40, 0x80), 0, prg_banks), // VRC4c
+            (21, _) => Self::new(false, (0x42, 0x84), 0, prg_banks),
+            (22, _) => Self::new(true, (0x02, 0x01), 1, prg_banks), // VRC2a
+            (23, 1) => Self::new(false, (0x01, 0x02), 0, prg_banks), // VRC4f
+            (23, 2) => Self::new(false, (0x04, 0x08), 0, prg_banks), // VRC4e
+            (23, 3) => Self::new(true, (0x01, 0x02), 0, prg_banks), // VRC2b
+            (23, _) => Self::new(false, (0x05, 0x0A), 0, prg_banks),
+            (25, 1) => Self::new(false, (0x02, 0x01), 0, prg_banks), // VRC4b
+            (25, 2) => Self::new(false, (0x08, 0x04), 0, prg_banks), // VRC4d
+            (25, 3) => Self::new(true, (0x02, 0x01), 0, prg_banks),  // VRC2c
+            (25, _) => Self::new(false, (0x0A, 0x05), 0, prg_banks),
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline]
+    fn register_index(&self, addr: u16) -> usize {
+        let mut index = 0;
+        if (addr & self.select_lines.0) != 0 {
+            index |= 0x01;
+        }
+        if (addr & self.select_lines.1) != 0 {
+            index |= 0x02;
+        }
+        index
+    }
+}
+impl Mapper for Vrc4 {
+    fn mirror(&self) -> Option<MirrorMode> {
+        Some(self.mirror)
+    }
+
+    fn interrupt_state(&self) -> bool {
+        self.irq.active
+    }
+
+    fn reset_interrupt(&mut self) {}
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        const PRG_BANK_SIZE_S: usize = 0x2000;
+
+        if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
+            if self.prg_ram_enabled {
+                self.prg_ram.read((addr.0 & 0x1FFF) as usize)
+            } else {
+                MapperReadResult::Address(None)
+            }
+        } else if addr.0 >= 0x8000 {
+            let second_last = (self.prg_banks as usize) * 2 - 2;
+            let bank = match (addr.0 >> 13) & 0x03 {
+                0 if self.prg_swap => second_last,
+                0 => self.prg_bank[0] as usize,
+                1 => self.prg_bank[1] as usize,
+                2 if self.prg_swap => self.prg_bank[0] as usize,
+                2 => second_last,
+                _ => second_last + 1,
+            };
+            let mapped_addr = bank * PRG_BANK_SIZE_S + ((addr.0 & 0x1FFF) as usize);
+            MapperReadResult::Address(Some(
+                mapped_addr % ((self.prg_banks as usize) * PRG_BANK_SIZE),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        const CHR_BANK_SIZE_S: usize = 0x0400;
+
+        if addr <= 0x1FFF {
+            let bank = ((addr >> 10u32) & 0x07).0 .0 as usize;
+            let mapped_addr = ((self.chr_bank[bank] >> self.chr_shift) as usize) * CHR_BANK_SIZE_S
+                + ((addr & 0x03FF).0 .0 as usize);
+            MapperReadResult::Address(Some(mapped_addr))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
+            if self.prg_ram_enabled {
+                self.prg_ram.write((addr.0 & 0x1FFF) as usize, data);
+            }
+        } else if addr.0 >= 0x8000 {
+            let register = addr.0 >> 12;
+            let index = self.register_index(addr.0);
+
+            match (register, index) {
+                (0x8, _) => self.prg_bank[0] = data.0 & 0x1F,
+                (0x9, 0) | (0x9, 1) => {
+                    self.mirror = if self.is_vrc2 {
+                        if (data.0 & 0x01) == 0 {
+                            MirrorMode::Vertical
+                        } else {
+                            MirrorMode::Horizontal
+                        }
+                    } else {
+                        match data.0 & 0x03 {
+                            0 => MirrorMode::Vertical,
+                            1 => MirrorMode::Horizontal,
+                            2 => MirrorMode::OneScreenLow,
+                            3 => MirrorMode::OneScreenHigh,
+                            _ => unreachable!(),
+                        }
+                    }
+                }
+                (0x9, _) => {
+                    if !self.is_vrc2 {
+                        self.prg_ram_enabled = (data.0 & 0x01) != 0;
+                        self.prg_swap = (data.0 & 0x02) != 0;
+                    }
+                }
+                (0xA, _) => self.prg_bank[1] = data.0 & 0x1F,
+                (0xB..=0xE, _) => {
+                    // Every 1k bank is set by two registers holding the lower and upper bits
+                    let bank = (((register - 0xB) as usize) * 2) + (index >> 1);
+                    if (index & 0x01) == 0 {
+                        self.chr_bank[bank] =
+                            (self.chr_bank[bank] & 0x01F0) | ((data.0 & 0x0F) as u16);
+                    } else {
+                        self.chr_bank[bank] =
+                            (self.chr_bank[bank] & 0x000F) | (((data.0 & 0x1F) as u16) << 4);
+                    }
+                }
+                (0xF, _) => {
+                    if !self.is_vrc2 {
+                        match index {
+                            0 => self.irq.set_latch_lo(data.0),
+                            1 => self.irq.set_latch_hi(data.0),
+                            2 => self.irq.set_control(data.0),
+                            _ => self.irq.acknowledge(),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = [0; 2];
+        self.prg_swap = false;
+        self.chr_bank = [0; 8];
+        self.mirror = MirrorMode::Vertical;
+        self.prg_ram_enabled = self.is_vrc2;
+        self.irq.reset();
+    }
+
+    #[inline]
+    fn prg_ram(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn clock(&mut self, cycles: u32) {
+        if !self.is_vrc2 {
+            for _ in 0..cycles {
+                self.irq.clock();
+            }
+        }
+    }
+}
+
+/// Konami VRC7, the VRC7a variant includes an FM synthesizer
+struct Vrc7 {
+    prg_banks: u8,
+    // CPU address line selecting the second register of each pair, A4 on VRC7a and A3 on VRC7b
+    select_line: u16,
+    prg_bank: [u8; 3],
+    chr_bank: [u8; 8],
+    mirror: MirrorMode,
+    prg_ram_enabled: bool,
+    audio_silenced: bool,
+    irq: VrcIrq,
+    prg_ram: PrgRam,
+    audio: Vrc7Audio,
+}
+impl Vrc7 {
+    fn new(submapper: u8, prg_banks: u8) -> Self {
+        let select_line = match submapper {
+            1 => 0x08,
+            2 => 0x10,
+            _ => 0x18,
+        };
+
+        Self {
+            prg_banks,
+            select_line,
+            prg_bank: [0; 3],
+            chr_bank: [0; 8],
+            mirror: MirrorMode::Vertical,
+            prg_ram_enabled: false,
+            audio_silenced: false,
+            irq: VrcIrq::new(),
+            prg_ram: PrgRam::new(PrgRam::DEFAULT_SIZE),
+            audio: Vrc7Audio::new(),
+        }
+    }
+}
+impl Mapper for Vrc7 {
+    fn mirror(&self) -> Option<MirrorMode> {
+        Some(self.mirror)
+    }
+
+    fn interrupt_state(&self) -> bool {
+        self.irq.active
+    }
+
+    fn reset_interrupt(&mut self) {}
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        const PRG_BANK_SIZE_S: usize = 0x2000;
+
+        if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
+            if self.prg_ram_enabled {
+                self.prg_ram.read((addr.0 & 0x1FFF) as usize)
+            } else {
+                MapperReadResult::Address(None)
+            }
+        } else if addr.0 >= 0x8000 {
+            let slot = ((addr.0 - 0x8000) >> 13) as usize;
+            let bank = if slot < 3 {
+                self.prg_bank[slot] as usize
+            } else {
+                (self.prg_banks as usize) * 2 - 1
+            };
+            let mapped_addr = bank * PRG_BANK_SIZE_S + ((addr.0 & 0x1FFF) as usize);
+            MapperReadResult::Address(Some(
+                mapped_addr % ((self.prg_banks as usize) * PRG_BANK_SIZE),
+            ))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        const CHR_BANK_SIZE_S: usize = 0x0400;
+
+        if addr <= 0x1FFF {
+            let bank = ((addr >> 10u32) & 0x07).0 .0 as usize;
+            let mapped_addr =
+                (self.chr_bank[bank] as usize) * CHR_BANK_SIZE_S + ((addr & 0x03FF).0 .0 as usize);
+            MapperReadResult::Address(Some(mapped_addr))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
+            if self.prg_ram_enabled {
+                self.prg_ram.write((addr.0 & 0x1FFF) as usize, data);
+            }
+        } else if addr.0 >= 0x8000 {
+            let second = (addr.0 & self.select_line) != 0;
+            match (addr.0 & 0xF000, second) {
+                (0x8000, false) => self.prg_bank[0] = data.0 & 0x3F,
+                (0x8000, true) => self.prg_bank[1] = data.0 & 0x3F,
+                (0x9000, _) if (addr.0 & 0x0020) != 0 => self.audio.write_data(data.0),
+                (0x9000, false) => self.prg_bank[2] = data.0 & 0x3F,
+                (0x9000, true) => self.audio.write_register(data.0),
+                (0xA000..=0xD000, _) => {
+                    let index = (((addr.0 - 0xA000) >> 11) as usize) | (second as usize);
+                    self.chr_bank[index] = data.0;
+                }
+                (0xE000, false) => {
+                    self.mirror = match data.0 & 0x03 {
+                        0 => MirrorMode::Vertical,
+                        1 => MirrorMode::Horizontal,
+                        2 => MirrorMode::OneScreenLow,
+                        3 => MirrorMode::OneScreenHigh,
+                        _ => unreachable!(),
+                    };
+                    self.prg_ram_enabled = (data.0 & 0x80) != 0;
+
+                    // Silencing the synthesizer also resets it
+                    self.audio_silenced = (data.0 & 0x40) != 0;
+                    if self.audio_silenced {
+                        self.audio.reset();
+                    }
+                }
+                (0xE000, true) => self.irq.set_latch(data.0),
+                (0xF000, false) => self.irq.set_control(data.0),
+                (0xF000, true) => self.irq.acknowledge(),
+                _ => {}
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = [0; 3];
+        self.chr_bank = [0; 8];
+        self.mirror = MirrorMode::Vertical;
+        self.prg_ram_enabled = false;
+        self.audio_silenced = false;
+        self.irq.reset();
+        self.audio.reset();
+    }
+
+    #[inline]
+    fn prg_ram(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn clock(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.irq.clock();
+            if !self.audio_silenced {
+                self.audio.clock();
+            }
+        }
+    }
+
+    fn audio_sample(&mut self) -> Sample {
+        if self.audio_silenced {
+            0.0
+        } else {
+            self.audio.sample()
+        }
+    }
+}
+
+/// Namco 163
+///
+/// CHR bank values of $E0 and above select the nametable RAM inside the console instead of
+/// CHR ROM, both for the pattern tables and the nametables.
+struct Namco163 {
+    prg_banks: u8,
+    prg_bank: [u8; 3],
+    chr_bank: [u8; 8],
+    nametable_bank: [u8; 4],
+    // Disables nametable RAM in place of the lower and upper pattern table
+    ciram_disabled: [bool; 2],
+    prg_ram_protect: u8,
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_active: bool,
+    prg_ram: PrgRam,
+    audio: Namco163Audio,
+}
+impl Namco163 {
+    fn new(prg_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            prg_bank: [0; 3],
+            chr_bank: [0; 8],
+            nametable_bank: [0; 4],
+            ciram_disabled: [false; 2],
+            prg_ram_protect: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_active: false,
+            prg_ram: PrgRam::new(PrgRam::DEFAULT_SIZE),
+            audio: Namco163Audio::new(),
+        }
+    }
+
+    #[inline]
+    fn prg_ram_writable(&self, addr: cpu6502::Address) -> bool {
+        // Each bit of the lower nibble protects one 2k window, the upper nibble has to be 4
+        let window = (addr.0 >> 11) & 0x03;
+        ((self.prg_ram_protect & 0xF0) == 0x40) && (((self.prg_ram_protect >> window) & 0x01) == 0)
+    }
+}
+impl Mapper for Namco163 {
+    fn mirror(&self) -> Option<MirrorMode> {
+        None
+    }
+
+    fn nametable(&self, index: usize) -> Option<Nametable> {
+        let bank = self.nametable_bank[index];
+        if bank >= 0xE0 {
+            Some(Nametable::Ciram((bank & 0x01) as usize))
+        } else {
+            Some(Nametable::Chr(bank as usize))
+        }
+    }
+
+    fn interrupt_state(&self) -> bool {
+        self.irq_active
+    }
+
+    fn reset_interrupt(&mut self) {}
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        const PRG_BANK_SIZE_S: usize = 0x2000;
+
+        match addr.0 {
+            0x4800..=0x4FFF => MapperReadResult::Data(Wrapping(self.audio.read_data())),
+            0x5000..=0x57FF => MapperReadResult::Data(Wrapping(self.irq_counter as u8)),
+            0x5800..=0x5FFF => {
+                let enabled_bit = if self.irq_enabled { 0x80 } else { 0x00 };
+                MapperReadResult::Data(Wrapping(((self.irq_counter >> 8) as u8) | enabled_bit))
+            }
+            0x6000..=0x7FFF => self.prg_ram.read((addr.0 & 0x1FFF) as usize),
+            0x8000..=0xFFFF => {
+                let slot = ((addr.0 - 0x8000) >> 13) as usize;
+                let bank = if slot < 3 {
+                    self.prg_bank[slot] as usize
+                } else {
+                    (self.prg_banks as usize) * 2 - 1
+                };
+                let mapped_addr = bank * PRG_BANK_SIZE_S + ((addr.0 & 0x1FFF) as usize);
+                MapperReadResult::Address(Some(
+                    mapped_addr % ((self.prg_banks as usize) * PRG_BANK_SIZE),
+                ))
+            }
+            _ => MapperReadResult::Address(None),
+        }
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        const CHR_BANK_SIZE_S: usize = 0x0400;
+
+        if addr <= 0x1FFF {
+            let slot = ((addr >> 10u32) & 0x07).0 .0 as usize;
+            let bank = self.chr_bank[slot];
+            if (bank >= 0xE0) && !self.ciram_disabled[slot >> 2] {
+                MapperReadResult::Ciram((bank & 0x01) as usize)
+            } else {
+                let mapped_addr =
+                    (bank as usize) * CHR_BANK_SIZE_S + ((addr & 0x03FF).0 .0 as usize);
+                MapperReadResult::Address(Some(mapped_addr))
+            }
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        match addr.0 {
+            0x4800..=0x4FFF => self.audio.write_data(data.0),
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | (data.0 as u16);
+                self.irq_active = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | (((data.0 & 0x7F) as u16) << 8);
+                self.irq_enabled = (data.0 & 0x80) != 0;
+                self.irq_active = false;
+            }
+            0x6000..=0x7FFF => {
+                if self.prg_ram_writable(addr) {
+                    self.prg_ram.write((addr.0 & 0x1FFF) as usize, data);
+                }
+            }
+            0x8000..=0xBFFF => self.chr_bank[((addr.0 - 0x8000) >> 11) as usize] = data.0,
+            0xC000..=0xDFFF => self.nametable_bank[((addr.0 - 0xC000) >> 11) as usize] = data.0,
+            0xE000..=0xE7FF => {
+                self.prg_bank[0] = data.0 & 0x3F;
+                self.audio.set_enabled((data.0 & 0x40) == 0);
+            }
+            0xE800..=0xEFFF => {
+                self.prg_bank[1] = data.0 & 0x3F;
+                self.ciram_disabled = [(data.0 & 0x40) != 0, (data.0 & 0x80) != 0];
+            }
+            0xF000..=0xF7FF => self.prg_bank[2] = data.0 & 0x3F,
+            0xF800..=0xFFFF => {
+                self.prg_ram_protect = data.0;
+                self.audio.write_address(data.0);
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = [0; 3];
+        self.chr_bank = [0; 8];
+        self.nametable_bank = [0; 4];
+        self.ciram_disabled = [false; 2];
+        self.prg_ram_protect = 0;
+        self.irq_counter = 0;
+        self.irq_enabled = false;
+        self.irq_active = false;
+        self.audio.reset();
+    }
+
+    #[inline]
+    fn prg_ram(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+
+    /// Some games keep their saves in the internal RAM of the chip, so it is stored after PRG RAM
+    fn save_data(&self) -> Option<Vec<u8>> {
+        let mut data: Vec<u8> = self.prg_ram.data().iter().map(|data| data.0).collect();
+        data.extend_from_slice(self.audio.ram());
+        Some(data)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let prg_ram = self.prg_ram.data_mut();
+        let (prg_data, audio_data) = data.split_at(prg_ram.len().min(data.len()));
+        for (target, source) in prg_ram.iter_mut().zip(prg_data.iter()) {
+            *target = Wrapping(*source);
+        }
+        for (target, source) in self.audio.ram_mut().iter_mut().zip(audio_data.iter()) {
+            *target = *source;
+        }
+    }
+
+    fn clock(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            // The 15 bit counter counts up and stops once it has reached $7FFF
+            if self.irq_enabled && (self.irq_counter < 0x7FFF) {
+                self.irq_counter += 1;
+                if self.irq_counter == 0x7FFF {
+                    self.irq_active = true;
+                }
+            }
+            self.audio.clock();
+        }
+    }
+
+    fn audio_sample(&mut self) -> Sample {
+        self.audio.sample()
+    }
+}
+
+/// Sunsoft FME-7 and the pin compatible 5B, which adds expansion audio
+struct Fme7 {
+    prg_banks: u8,
+    command: u8,
+    chr_bank: [u8; 8],
+    prg_bank: [u8; 3],
+    prg_bank_6000: u8,
+    ram_selected: bool,
+    ram_enabled: bool,
+    mirror: MirrorMode,
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq_active: bool,
+    prg_ram: PrgRam,
+    audio: Sunsoft5bAudio,
+}
+impl Fme7 {
+    fn new(prg_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            command: 0,
+            chr_bank: [0; 8],
+            prg_bank: [0; 3],
+            prg_bank_6000: 0,
+            ram_selected: false,
+            ram_enabled: false,
+            mirror: MirrorMode::Vertical,
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0,
+            irq_active: false,
+            prg_ram: PrgRam::new(PrgRam::DEFAULT_SIZE),
+            audio: Sunsoft5bAudio::new(),
+        }
+    }
+
+    fn write_parameter(&mut self, data: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_bank[self.command as usize] = data,
+            0x8 => {
+                self.prg_bank_6000 = data & 0x3F;
+                self.ram_selected = (data & 0x40) != 0;
+                self.ram_enabled = (data & 0x80) != 0;
+            }
+            0x9..=0xB => self.prg_bank[(self.command - 0x9) as usize] = data & 0x3F,
+            0xC => {
+                self.mirror = match data & 0x03 {
+                    0 => MirrorMode::Vertical,
+                    1 => MirrorMode::Horizontal,
+                    2 => MirrorMode::OneScreenLow,
+                    3 => MirrorMode::OneScreenHigh,
+                    _ => unreachable!(),
+                };
+            }
+            0xD => {
+                self.irq_enabled = (data & 0x01) != 0;
+                self.irq_counter_enabled = (data & 0x80) != 0;
+                self.irq_active = false;
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | (data as u16),
+            0xF => self.irq_counter = (self.irq_counter & 0x00FF) | ((data as u16) << 8),
+            _ => unreachable!(),
+        }
+    }
+}
+impl Mapper for Fme7 {
+    fn mirror(&self) -> Option<MirrorMode> {
+        Some(self.mirror)
+    }
+
+    fn interrupt_state(&self) -> bool {
+        self.irq_active
+    }
+
+    fn reset_interrupt(&mut self) {}
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        const PRG_BANK_SIZE_S: usize = 0x2000;
+
+        let bank = match addr.0 {
+            0x6000..=0x7FFF => {
+                if !self.ram_selected {
+                    self.prg_bank_6000 as usize
+                } else if self.ram_enabled {
+                    return self.prg_ram.read((addr.0 & 0x1FFF) as usize);
+                } else {
+                    // Open bus
+                    return MapperReadResult::Address(None);
+                }
+            }
+            0x8000..=0xDFFF => self.prg_bank[((addr.0 - 0x8000) >> 13) as usize] as usize,
+            0xE000..=0xFFFF => (self.prg_banks as usize) * 2 - 1,
+            _ => return MapperReadResult::Address(None),
+        };
+
+        let mapped_addr = bank * PRG_BANK_SIZE_S + ((addr.0 & 0x1FFF) as usize);
+        MapperReadResult::Address(Some(
+            mapped_addr % ((self.prg_banks as usize) * PRG_BANK_SIZE),
+        ))
+    }
+
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        const CHR_BANK_SIZE_S: usize = 0x0400;
+
+        if addr <= 0x1FFF {
+            let bank = ((addr >> 10u32) & 0x07).0 .0 as usize;
+            let mapped_addr =
+                (self.chr_bank[bank] as usize) * CHR_BANK_SIZE_S + ((addr & 0x03FF).0 .0 as usize);
+            MapperReadResult::Address(Some(mapped_addr))
+        } else {
+            MapperReadResult::Address(None)
+        }
+    }
+
+    fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        match addr.0 {
+            0x6000..=0x7FFF => {
+                if self.ram_selected && self.ram_enabled {
+                    self.prg_ram.write((addr.0 & 0x1FFF) as usize, data);
+                }
+            }
+            0x8000..=0x9FFF => self.command = data.0 & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(data.0),
+            0xC000..=0xDFFF => self.audio.write_register(data.0),
+            0xE000..=0xFFFF => self.audio.write_data(data.0),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.command = 0;
+        self.chr_bank = [0; 8];
+        self.prg_bank = [0; 3];
+        self.prg_bank_6000 = 0;
+        self.ram_selected = false;
+        self.ram_enabled = false;
+        self.mirror = MirrorMode::Vertical;
+        self.irq_enabled = false;
+        self.irq_counter_enabled = false;
+        self.irq_counter = 0;
+        self.irq_active = false;
+        self.audio = Sunsoft5bAudio::new();
+    }
+
+    #[inline]
+    fn prg_ram(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn clock(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            if self.irq_counter_enabled {
+                self.irq_counter = self.irq_counter.wrapping_sub(1);
+                if (self.irq_counter == 0xFFFF) && self.irq_enabled {
+                    self.irq_active = true;
+                }
+            }
+            self.audio.clock();
+        }
+    }
+
+    fn audio_sample(&mut self) -> Sample {
+        self.audio.sample()
+    }
+}
+
+/// Bandai FCG-1/FCG-2 and LZ93D50 boards
+///
+/// The FCG chips have their registers at $6000, the LZ93D50 at $8000 and an optional
+/// serial EEPROM. Mapper 153 has battery backed PRG RAM and an outer PRG bank instead.
+struct BandaiFcg {
+    prg_banks: u8,
+    // Registers are mirrored at $6000 (FCG) and/or $8000 (LZ93D50)
+    register_ranges: (bool, bool),
+    has_chr_banking: bool,
+    has_prg_ram: bool,
+    prg_bank: u8,
+    outer_prg_bank: u8,
+    chr_bank: [u8; 8],
+    mirror: MirrorMode,
+    prg_ram_enabled: bool,
+    // The LZ93D50 loads the counter from a latch, the FCG writes the counter directly
+    irq_latched: bool,
+    irq_enabled: bool,
+    irq_latch: u16,
+    irq_counter: u16,
+    irq_active: bool,
+    eeprom: Option<Eeprom>,
+    prg_ram: PrgRam,
+}
+impl BandaiFcg {
+    fn from_id(id: u8, submapper: u8, prg_banks: u8) -> Self {
+        let mapper = Self {
+            prg_banks,
+            register_ranges: (true, true),
+            has_chr_banking: true,
+            has_prg_ram: false,
+            prg_bank: 0,
+            outer_prg_bank: 0,
+            chr_bank: [0; 8],
+            mirror: MirrorMode::Vertical,
+            prg_ram_enabled: false,
+            irq_latched: true,
+            irq_enabled: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_active: false,
+            eeprom: None,
+            prg_ram: PrgRam::new(PrgRam::DEFAULT_SIZE),
+        };
+
+        match (id, submapper) {
+            (16, 4) => Self {
+                register_ranges: (true, false),
+                irq_latched: false,
+                ..mapper
+            },
+            (16, 5) => Self {
+                register_ranges: (false, true),
+                eeprom: Some(Eeprom::new(EepromKind::X24C02)),
+                ..mapper
+            },
+            // Without a submapper both register ranges are decoded, the EEPROM is assumed
+            (16, _) => Self {
+                eeprom: Some(Eeprom::new(EepromKind::X24C02)),
+                ..mapper
+            },
+            (153, _) => Self {
+                register_ranges: (false, true),
+                has_chr_banking: false,
+                has_prg_ram: true,
+                ..mapper
+            },
+            // Datach Joint ROM System, the barcode reader is not emulated
+            (157, _) => Self {
+                register_ranges: (false, true),
+                has_chr_banking: false,
+                eeprom: Some(Eeprom::new(EepromKind::X24C02)),
+                ..mapper
+            },
+            (159, _) => Self {
+                register_ranges: (false, true),
+                eeprom: Some(Eeprom::new(EepromKind::X24C01)),
+                ..mapper
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0x0..=0x7 => {
+                if self.has_prg_ram {
+                    // Mapper 153 uses the CHR registers to select a 256k outer PRG bank
+                    self.outer_prg_bank = data & 0x01;
+                } else {
+                    self.chr_bank[register as usize] = data;
+                }
+            }
+            0x8 => self.prg_bank = data & 0x0F,
+            0x9 => {
+                self.mirror = match data & 0x03 {
+                    0 => MirrorMode::Vertical,
+                    1 => MirrorMode::Horizontal,
+                    2 => MirrorMode::OneScreenLow,
+                    3 => MirrorMode::OneScreenHigh,
+                    _ => unreachable!(),
+                };
+            }
+            0xA => {
+                self.irq_enabled = (data & 0x01) != 0;
+                self.irq_active = false;
+                if self.irq_latched {
+                    self.irq_counter = self.irq_latch;
+                }
+            }
+            0xB => {
+                if self.irq_latched {
+                    self.irq_latch = (self.irq_latch & 0xFF00) | (data as u16);
+                } else {
+                    self.irq_counter = (self.irq_counter & 0xFF00) | (data as u16);
+                }
+            }
+            0xC => {
+                if self.irq_latched {
+                    self.irq_latch = (self.irq_latch & 0x00FF) | ((data as u16) << 8);
+                } else {
+                    self.irq_counter = (self.irq_counter & 0x00FF) | ((data as u16) << 8);
+                }
+            }
+            0xD => {
+                if self.has_prg_ram {
+                    self.prg_ram_enabled = (data & 0x20) != 0;
+                } else if let Some(eeprom) = &mut self.eeprom {
+                    eeprom.write((data & 0x20) != 0, (data & 0x40) != 0);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+impl Mapper for BandaiFcg {
+    fn mirror(&self) -> Option<MirrorMode> {
+        Some(self.mirror)
+    }
+
+    fn interrupt_state(&self) -> bool {
+        self.irq_active
+    }
+
+    fn reset_interrupt(&mut self) {}
+
+    fn on_scanline(&mut self) {}
+
+    fn cpu_read(&mut self, addr: cpu6502::Address) -> MapperReadResult {
+        if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
+            if self.has_prg_ram {
+                if self.prg_ram_enabled {
+                    self.prg_ram.read((addr.0 & 0x1FFF) as usize)
+                } else {
+                    MapperReadResult::Address(None)
+                }
+            } else if let Some(eeprom) = &self.eeprom {
+                // The EEPROM data line appears on bit 4
+                MapperReadResult::Data(Wrapping(if eeprom.output() { 0x10 } else { 0x00 }))
+            } else {
+                MapperReadResult::Address(None)
+            }
+        } else if addr.0 >= 0x8000 {
+            let outer_bank = (self.outer_prg_bank as usize) << 4;
+            let bank = if addr.0 <= 0xBFFF {
+                outer_bank | (self.prg_bank as usize)
+            } else {
+                outer_bank | 0x0F
+            };
+            let mapped_addr = bank * PRG_BANK_SIZE + ((addr.0 & 0x3FFF) as usize);
             MapperReadResult::Address(Some(
-                (self.prg_bank as usize) * 2 * PRG_BANK_SIZE + ((addr.0 & 0x7FFF) as usize),
+                mapped_addr % ((self.prg_banks as usize) * PRG_BANK_SIZE),
             ))
         } else {
             MapperReadResult::Address(None)
         }
     }
 
-    fn ppu_read(&self, addr: ppu2C02::Address) -> MapperReadResult {
+    fn ppu_read(&mut self, addr: ppu2C02::Address) -> MapperReadResult {
+        const CHR_BANK_SIZE_S: usize = 0x0400;
+
         if addr <= 0x1FFF {
-            MapperReadResult::Address(Some(
-                (self.chr_bank as usize) * CHR_BANK_SIZE + (addr.0 .0 as usize),
-            ))
+            if self.has_chr_banking {
+                let bank = ((addr >> 10u32) & 0x07).0 .0 as usize;
+                let mapped_addr = (self.chr_bank[bank] as usize) * CHR_BANK_SIZE_S
+                    + ((addr & 0x03FF).0 .0 as usize);
+                MapperReadResult::Address(Some(mapped_addr))
+            } else {
+                MapperReadResult::Address(Some(addr.0 .0 as usize))
+            }
         } else {
             MapperReadResult::Address(None)
         }
     }
 
     fn cpu_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
-        if addr.0 >= 0x8000 {
-            self.chr_bank = data.0 & 0x03;
-            self.prg_bank = (data.0 >> 4) & 0x03;
+        if (addr.0 >= 0x6000) && (addr.0 <= 0x7FFF) {
+            if self.has_prg_ram {
+                if self.prg_ram_enabled {
+                    self.prg_ram.write((addr.0 & 0x1FFF) as usize, data);
+                }
+            } else if self.register_ranges.0 {
+                self.write_register(addr.0 & 0x000F, data.0);
+            }
+        } else if (addr.0 >= 0x8000) && self.register_ranges.1 {
+            self.write_register(addr.0 & 0x000F, data.0);
         }
     }
 
     fn reset(&mut self) {
         self.prg_bank = 0;
-        self.chr_bank = 0;
+        self.outer_prg_bank = 0;
+        self.chr_bank = [0; 8];
+        self.mirror = MirrorMode::Vertical;
+        self.prg_ram_enabled = false;
+        self.irq_enabled = false;
+        self.irq_latch = 0;
+        self.irq_counter = 0;
+        self.irq_active = false;
+    }
+
+    fn prg_ram(&mut self) -> Option<&mut PrgRam> {
+        if self.has_prg_ram {
+            Some(&mut self.prg_ram)
+        } else {
+            None
+        }
+    }
+
+    fn clock(&mut self, cycles: u32) {
+        if self.irq_enabled {
+            for _ in 0..cycles {
+                if self.irq_counter == 0 {
+                    self.irq_active = true;
+                }
+                self.irq_counter = self.irq_counter.wrapping_sub(1);
+            }
+        }
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if let Some(eeprom) = &self.eeprom {
+            Some(eeprom.data().to_vec())
+        } else if self.has_prg_ram {
+            Some(self.prg_ram.data().iter().map(|data| data.0).collect())
+        } else {
+            None
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        if let Some(eeprom) = &mut self.eeprom {
+            eeprom.load_data(data);
+        } else if self.has_prg_ram {
+            for (target, source) in self.prg_ram.data_mut().iter_mut().zip(data.iter()) {
+                *target = Wrapping(*source);
+            }
+        }
     }
 }
 
-fn get_mapper_from_id(id: u8, prg_banks: u8) -> Option<EmuRef<dyn Mapper>> {
+fn get_mapper_from_id(
+    id: u8,
+    submapper: u8,
+    prg_banks: u8,
+    chr_banks: u8,
+) -> Option<EmuRef<dyn Mapper>> {
     // This is only a very small subset of all existing mappers,
     // but these will enable most Nintendo first-party titles to be emulated
     match id {
         0 => Some(make_ref(NRom::new(prg_banks))),
         1 => Some(make_ref(Mmc1::new(prg_banks))),
-        2 => Some(make_ref(UxRom::new(prg_banks))),
-        3 => Some(make_ref(CNRom::new(prg_banks))),
-        4 => Some(make_ref(Mmc3::new(prg_banks))),
-        7 => Some(make_ref(AxRom::new())),
+        2 => Some(make_ref(UxRom::new(submapper, prg_banks))),
+        3 => Some(make_ref(CNRom::new(submapper, prg_banks))),
+        4 => Some(make_ref(Mmc3::new(prg_banks, submapper == 4))),
+        5 => Some(make_ref(Mmc5::new(prg_banks, chr_banks))),
+        7 => Some(make_ref(AxRom::new(submapper))),
+        9 => Some(make_ref(Mmc2::new(prg_banks, false))),
+        10 => Some(make_ref(Mmc2::new(prg_banks, true))),
+        11 => Some(make_ref(ColorDreams::new(prg_banks, chr_banks))),
+        16 => Some(make_ref(BandaiFcg::from_id(id, submapper, prg_banks))),
+        19 => Some(make_ref(Namco163::new(prg_banks))),
+        21 | 22 | 23 | 25 => Some(make_ref(Vrc4::from_id(id, submapper, prg_banks))),
+        24 => Some(make_ref(Vrc6::new(prg_banks, false))),
+        26 => Some(make_ref(Vrc6::new(prg_banks, true))),
+        34 => Some(make_ref(BnRom::new(submapper, prg_banks, chr_banks))),
         66 => Some(make_ref(GxRom::new())),
+        69 => Some(make_ref(Fme7::new(prg_banks))),
+        71 => Some(make_ref(Camerica::new(submapper, prg_banks))),
+        79 => Some(make_ref(Nina003::new(prg_banks, chr_banks))),
+        85 => Some(make_ref(Vrc7::new(submapper, prg_banks))),
+        118 => Some(make_ref(Mmc3::with_board(prg_banks, Mmc3Board::TxSRom))),
+        119 => Some(make_ref(Mmc3::with_board(prg_banks, Mmc3Board::TqRom))),
+        140 => Some(make_ref(Jaleco::new(prg_banks, chr_banks))),
+        153 | 157 | 159 => Some(make_ref(BandaiFcg::from_id(id, submapper, prg_banks))),
+        180 => Some(make_ref(UnRomReversed::new(prg_banks))),
+        206 => Some(make_ref(Mmc3::with_board(prg_banks, Mmc3Board::Namco108))),
         _ => None,
     }
 }
@@ -962,6 +3849,9 @@ pub struct Cartridge {
     cpu_adapter: EmuRef<CartridgeCpuAdapter>,
     ppu_adapter: EmuRef<CartridgePpuAdapter>,
     mirror: MirrorMode,
+    save_path: Option<PathBuf>,
+    region: Region,
+    name: Option<String>,
 }
 impl Cartridge {
     const CPU_RANGE: AddressRange<cpu6502::Address> =
@@ -973,71 +3863,232 @@ impl Cartridge {
         mapper: EmuRef<dyn Mapper>,
         prg_rom: Vec<u8>,
         chr_rom: Vec<u8>,
-        chr_is_ram: bool,
+        chr_ram_size: usize,
         mirror: MirrorMode,
+        save_path: Option<PathBuf>,
+        region: Region,
     ) -> Self {
+        let nametable_ram_size = if mirror == MirrorMode::FourScreen {
+            mapper.borrow().nametable_ram_size().max(NAMETABLE_RAM_SIZE)
+        } else {
+            mapper.borrow().nametable_ram_size()
+        };
+
         let cpu_adapter = make_ref(CartridgeCpuAdapter::new(clone_ref(&mapper), prg_rom));
         let ppu_adapter = make_ref(CartridgePpuAdapter::new(
             clone_ref(&mapper),
             chr_rom,
-            chr_is_ram,
+            vec![0; chr_ram_size],
+            vec![0; nametable_ram_size],
         ));
 
-        Self {
-            mapper,
-            cpu_adapter,
-            ppu_adapter,
-            mirror,
-        }
+        Self {
+            mapper,
+            cpu_adapter,
+            ppu_adapter,
+            mirror,
+            save_path,
+            region,
+            name: None,
+        }
+    }
+
+    /// Name of the game if it was found in the database
+    #[inline]
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Copy of the work RAM currently mapped to $6000-$7FFF, in CPU address order.
+    /// `None` if the board has no work RAM or something else is mapped there right now.
+    /// No board changes state on reads from that range, so this doesn't affect the game.
+    pub fn prg_ram_window(&self) -> Option<Vec<u8>> {
+        let mut mapper = self.mapper.borrow_mut();
+        mapper.prg_ram()?;
+        (0x6000..=0x7FFF)
+            .map(|addr| match mapper.cpu_read(Wrapping(addr)) {
+                MapperReadResult::Data(data) => Some(data.0),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The region the game was made for according to its header
+    #[inline]
+    pub const fn region(&self) -> Region {
+        self.region
+    }
+
+    #[inline]
+    fn get_cpu_adapter(&self) -> EmuRef<CartridgeCpuAdapter> {
+        clone_ref(&self.cpu_adapter)
+    }
+    #[inline]
+    fn get_ppu_adapter(&self) -> EmuRef<CartridgePpuAdapter> {
+        clone_ref(&self.ppu_adapter)
+    }
+
+    #[inline]
+    fn mirror(&self) -> MirrorMode {
+        // Boards with extra VRAM ignore the mirroring selected by the mapper
+        if self.mirror == MirrorMode::FourScreen {
+            MirrorMode::FourScreen
+        } else if let Some(mapper_mirror) = self.mapper.borrow().mirror() {
+            mapper_mirror
+        } else {
+            self.mirror
+        }
+    }
+
+    #[inline]
+    fn reset_mapper(&mut self) {
+        self.mapper.borrow_mut().reset();
+    }
+
+    #[inline]
+    fn interrupt_state(&self) -> bool {
+        self.mapper.borrow().interrupt_state()
+    }
+
+    #[inline]
+    fn reset_interrupt(&mut self) {
+        self.mapper.borrow_mut().reset_interrupt();
+    }
+
+    #[inline]
+    pub fn on_scanline(&mut self) {
+        self.mapper.borrow_mut().on_scanline();
+    }
+
+    fn nametable(&self, index: usize) -> Nametable {
+        if let Some(nametable) = self.mapper.borrow().nametable(index) {
+            nametable
+        } else {
+            match self.mirror() {
+                MirrorMode::Horizontal => Nametable::Ciram(index >> 1),
+                MirrorMode::Vertical => Nametable::Ciram(index & 0x01),
+                MirrorMode::OneScreenLow => Nametable::Ciram(0),
+                MirrorMode::OneScreenHigh => Nametable::Ciram(1),
+                MirrorMode::FourScreen => {
+                    if index < 2 {
+                        Nametable::Ciram(index)
+                    } else {
+                        Nametable::Cartridge(index - 2)
+                    }
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn nametable_read(&self, addr: ppu2C02::Address, ciram_data: ppu2C02::Word) -> ppu2C02::Word {
+        self.mapper.borrow_mut().nametable_read(addr, ciram_data)
+    }
+
+    #[inline]
+    fn nametable_write(&self, addr: ppu2C02::Address, data: ppu2C02::Word) {
+        self.mapper.borrow_mut().nametable_write(addr, data);
+    }
+
+    #[inline]
+    pub fn set_ppu_fetch(&mut self, fetch: PpuFetch) {
+        self.mapper.borrow_mut().set_ppu_fetch(fetch);
+    }
+
+    #[inline]
+    pub fn on_ppu_register_write(&mut self, addr: cpu6502::Address, data: cpu6502::Word) {
+        self.mapper.borrow_mut().on_ppu_register_write(addr, data);
     }
 
     #[inline]
-    fn get_cpu_adapter(&self) -> EmuRef<CartridgeCpuAdapter> {
-        clone_ref(&self.cpu_adapter)
+    pub fn on_ppu_address(&mut self, addr: ppu2C02::Address, ppu_cycle: u64) {
+        self.mapper.borrow_mut().on_ppu_address(addr, ppu_cycle);
     }
+
     #[inline]
-    fn get_ppu_adapter(&self) -> EmuRef<CartridgePpuAdapter> {
-        clone_ref(&self.ppu_adapter)
+    fn clock(&mut self, cycles: u32) {
+        self.mapper.borrow_mut().clock(cycles);
     }
 
     #[inline]
-    fn mirror(&self) -> MirrorMode {
-        if let Some(mapper_mirror) = self.mapper.borrow().mirror() {
-            mapper_mirror
+    pub fn audio_sample(&self) -> Sample {
+        self.mapper.borrow_mut().audio_sample()
+    }
+
+    fn load_save(&self) {
+        if let Some(path) = &self.save_path {
+            if let Ok(data) = std::fs::read(path) {
+                self.mapper.borrow_mut().load_save_data(&data);
+            }
+        }
+    }
+
+    /// Writes battery backed memory next to the ROM file, if the cartridge has any
+    pub fn save(&self) -> std::io::Result<()> {
+        if let Some(path) = &self.save_path {
+            if let Some(data) = self.mapper.borrow().save_data() {
+                std::fs::write(path, data)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn read_chr(&self, offset: usize) -> ppu2C02::Word {
+        let ppu_adapter = self.ppu_adapter.borrow();
+        if ppu_adapter.chr_rom.is_empty() {
+            Wrapping(0)
         } else {
-            self.mirror
+            Wrapping(ppu_adapter.chr_rom[offset % ppu_adapter.chr_rom.len()])
         }
     }
 
     #[inline]
-    fn reset_mapper(&mut self) {
-        self.mapper.borrow_mut().reset();
+    fn read_nametable_ram(&self, offset: usize) -> ppu2C02::Word {
+        let ppu_adapter = self.ppu_adapter.borrow();
+        if ppu_adapter.nametable_ram.is_empty() {
+            Wrapping(0)
+        } else {
+            Wrapping(ppu_adapter.nametable_ram[offset % ppu_adapter.nametable_ram.len()])
+        }
     }
 
     #[inline]
-    fn interrupt_state(&self) -> bool {
-        self.mapper.borrow().interrupt_state()
+    fn write_nametable_ram(&self, offset: usize, data: ppu2C02::Word) {
+        let mut ppu_adapter = self.ppu_adapter.borrow_mut();
+        if !ppu_adapter.nametable_ram.is_empty() {
+            let len = ppu_adapter.nametable_ram.len();
+            ppu_adapter.nametable_ram[offset % len] = data.0;
+        }
     }
 
+    /// Gives the cartridge access to the nametable RAM inside the console
     #[inline]
-    fn reset_interrupt(&mut self) {
-        self.mapper.borrow_mut().reset_interrupt();
+    fn set_vram(&self, vram: Option<EmuRef<Vram>>) {
+        self.ppu_adapter.borrow_mut().vram = vram;
     }
 
+    /// Game Genie codes are applied between the cartridge and the console
     #[inline]
-    pub fn on_scanline(&mut self) {
-        self.mapper.borrow_mut().on_scanline();
+    fn set_cheats(&self, cheats: Option<EmuRef<CheatList>>) {
+        self.cpu_adapter.borrow_mut().cheats = cheats;
     }
 }
 
 struct CartridgeCpuAdapter {
     mapper: EmuRef<dyn Mapper>,
     prg_rom: Vec<u8>,
+    cheats: Option<EmuRef<CheatList>>,
 }
 impl CartridgeCpuAdapter {
     #[inline]
     const fn new(mapper: EmuRef<dyn Mapper>, prg_rom: Vec<u8>) -> Self {
-        Self { mapper, prg_rom }
+        Self {
+            mapper,
+            prg_rom,
+            cheats: None,
+        }
     }
 }
 impl BusComponent<cpu6502::Address, cpu6502::Word> for CartridgeCpuAdapter {
@@ -1051,37 +4102,73 @@ impl BusComponent<cpu6502::Address, cpu6502::Word> for CartridgeCpuAdapter {
     }
 
     fn read(&mut self, address: cpu6502::Address) -> cpu6502::Word {
-        match self
-            .mapper
-            .borrow()
-            .cpu_read(address + Cartridge::CPU_RANGE.start)
-        {
+        let address = address + Cartridge::CPU_RANGE.start;
+        let data = match self.mapper.borrow_mut().cpu_read(address) {
             MapperReadResult::Data(data) => data,
             MapperReadResult::Address(Some(mapped_addr)) => Wrapping(self.prg_rom[mapped_addr]),
             _ => Wrapping(0),
+        };
+
+        match &self.cheats {
+            Some(cheats) => Wrapping(cheats.borrow().patch_read(address.0, data.0)),
+            None => data,
         }
     }
 
-    #[inline]
     fn write(&mut self, address: cpu6502::Address, data: cpu6502::Word) {
-        self.mapper
-            .borrow_mut()
-            .cpu_write(address + Cartridge::CPU_RANGE.start, data);
+        let mut mapper = self.mapper.borrow_mut();
+        let address = address + Cartridge::CPU_RANGE.start;
+
+        let data = if mapper.has_bus_conflicts() {
+            // The ROM drives the data bus at the same time as the CPU
+            match mapper.cpu_read(address) {
+                MapperReadResult::Address(Some(mapped_addr)) => {
+                    data & Wrapping(self.prg_rom[mapped_addr])
+                }
+                _ => data,
+            }
+        } else {
+            data
+        };
+        mapper.cpu_write(address, data);
     }
 }
 
 struct CartridgePpuAdapter {
     mapper: EmuRef<dyn Mapper>,
     chr_rom: Vec<u8>,
-    chr_is_ram: bool,
+    chr_ram: Vec<u8>,
+    nametable_ram: Vec<u8>,
+    vram: Option<EmuRef<Vram>>,
 }
 impl CartridgePpuAdapter {
     #[inline]
-    const fn new(mapper: EmuRef<dyn Mapper>, chr_rom: Vec<u8>, chr_is_ram: bool) -> Self {
+    const fn new(
+        mapper: EmuRef<dyn Mapper>,
+        chr_rom: Vec<u8>,
+        chr_ram: Vec<u8>,
+        nametable_ram: Vec<u8>,
+    ) -> Self {
         Self {
             mapper,
             chr_rom,
-            chr_is_ram,
+            chr_ram,
+            nametable_ram,
+            vram: None,
+        }
+    }
+
+    fn chr_ram_address(&self, address: ppu2C02::Address) -> Option<usize> {
+        if self.chr_ram.is_empty() {
+            None
+        } else if self.chr_rom.is_empty() {
+            // Boards without any CHR ROM have their CHR RAM mapped directly
+            Some(((address & 0x1FFF).0 .0 as usize) % self.chr_ram.len())
+        } else {
+            self.mapper
+                .borrow()
+                .chr_ram_address(address)
+                .map(|ram_addr| ram_addr % self.chr_ram.len())
         }
     }
 }
@@ -1096,12 +4183,16 @@ impl BusComponent<ppu2C02::Address, ppu2C02::Word> for CartridgePpuAdapter {
     }
 
     fn read(&mut self, address: ppu2C02::Address) -> ppu2C02::Word {
-        if self.chr_is_ram {
-            Wrapping(self.chr_rom[(address & 0x1FFF).0 .0 as usize])
+        if let Some(ram_addr) = self.chr_ram_address(address) {
+            Wrapping(self.chr_ram[ram_addr])
         } else {
-            match self.mapper.borrow().ppu_read(address) {
+            match self.mapper.borrow_mut().ppu_read(address) {
                 MapperReadResult::Data(data) => data,
                 MapperReadResult::Address(Some(mapped_addr)) => Wrapping(self.chr_rom[mapped_addr]),
+                MapperReadResult::Ciram(index) => match &self.vram {
+                    Some(vram) => vram.borrow_mut().read_ciram(index, address),
+                    None => Wrapping(0),
+                },
                 _ => Wrapping(0),
             }
         }
@@ -1109,27 +4200,90 @@ impl BusComponent<ppu2C02::Address, ppu2C02::Word> for CartridgePpuAdapter {
 
     #[inline]
     fn write(&mut self, address: ppu2C02::Address, data: ppu2C02::Word) {
-        if self.chr_is_ram {
-            self.chr_rom[(address & 0x1FFF).0 .0 as usize] = data.0;
+        if let Some(ram_addr) = self.chr_ram_address(address) {
+            self.chr_ram[ram_addr] = data.0;
+        } else if let MapperReadResult::Ciram(index) = self.mapper.borrow_mut().ppu_read(address) {
+            // Nametable RAM mapped into pattern table space is writable like CHR RAM
+            if let Some(vram) = &self.vram {
+                vram.borrow_mut().write_ciram(index, address, data);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(std::io::Error),
+    Patch(PatchError),
+    /// The file does not start with the iNES signature
+    InvalidMagic,
+    TruncatedPrg,
+    TruncatedChr,
+    UnsupportedMapper(u8),
+    InvalidHeader(&'static str),
+}
+impl Display for CartridgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CartridgeError::Io(err) => f.write_fmt(format_args!("Could not read file: {}", err)),
+            CartridgeError::Patch(err) => {
+                f.write_fmt(format_args!("Could not apply patch: {}", err))
+            }
+            CartridgeError::InvalidMagic => f.write_str("Not an iNES file"),
+            CartridgeError::TruncatedPrg => f.write_str("File ends before the end of PRG ROM"),
+            CartridgeError::TruncatedChr => f.write_str("File ends before the end of CHR ROM"),
+            CartridgeError::UnsupportedMapper(id) => {
+                f.write_fmt(format_args!("Mapper {} is not supported", id))
+            }
+            CartridgeError::InvalidHeader(reason) => {
+                f.write_fmt(format_args!("Invalid header: {}", reason))
+            }
+        }
+    }
+}
+impl std::error::Error for CartridgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CartridgeError::Io(err) => Some(err),
+            CartridgeError::Patch(err) => Some(err),
+            _ => None,
         }
     }
 }
+impl From<std::io::Error> for CartridgeError {
+    #[inline]
+    fn from(err: std::io::Error) -> Self {
+        CartridgeError::Io(err)
+    }
+}
+impl From<PatchError> for CartridgeError {
+    #[inline]
+    fn from(err: PatchError) -> Self {
+        CartridgeError::Patch(err)
+    }
+}
 
 struct INesHeader {
     prg_banks: u8,
     chr_banks: u8,
     mapper_1: u8,
     mapper_2: u8,
-    _prg_ram_size: u8,
-    _tv_system_1: u8,
-    _tv_system_2: u8,
+    // PRG RAM size in iNES, upper mapper bits and submapper in NES 2.0
+    mapper_3: u8,
+    tv_system_1: u8,
+    // TV system in iNES, PRG RAM sizes in NES 2.0
+    prg_ram: u8,
+    // CHR RAM sizes in NES 2.0
+    chr_ram: u8,
+    // CPU/PPU timing in NES 2.0
+    timing: u8,
 }
 impl INesHeader {
-    pub fn from_reader(reader: &mut BinReader) -> Option<Self> {
+    pub fn from_reader(reader: &mut BinReader) -> Result<Self, CartridgeError> {
         // The file ID is a fixed pattern of 4 bytes that has to match exactly
         let mut file_id: [u8; 4] = [0; 4];
         if reader.read_into(&mut file_id) != 4 {
-            return None;
+            return Err(CartridgeError::InvalidMagic);
         }
         // This byte pattern resolves to "NES" followed by an MSDOS end-of-file character
         if (file_id[0] != 0x4E)
@@ -1137,77 +4291,206 @@ impl INesHeader {
             || (file_id[2] != 0x53)
             || (file_id[3] != 0x1A)
         {
-            return None;
+            return Err(CartridgeError::InvalidMagic);
         }
 
-        let prg_banks = reader.read_byte()?;
-        let chr_banks = reader.read_byte()?;
-        let mapper_1 = reader.read_byte()?;
-        let mapper_2 = reader.read_byte()?;
-        let prg_ram_size = reader.read_byte()?;
-        let tv_system_1 = reader.read_byte()?;
-        let tv_system_2 = reader.read_byte()?;
-        let mut unused: [u8; 5] = [0; 5];
-        if reader.read_into(&mut unused) != 5 {
-            return None;
+        let mut fields: [u8; 12] = [0; 12];
+        if reader.read_into(&mut fields) != 12 {
+            return Err(CartridgeError::InvalidHeader("file is too short"));
         }
 
-        Some(Self {
-            prg_banks,
-            chr_banks,
-            mapper_1,
-            mapper_2,
-            _prg_ram_size: prg_ram_size,
-            _tv_system_1: tv_system_1,
-            _tv_system_2: tv_system_2,
-        })
+        // Bytes 11 to 15 are mostly unused in iNES, RAM sizes, timing and console type in NES 2.0
+        let header = Self {
+            prg_banks: fields[0],
+            chr_banks: fields[1],
+            mapper_1: fields[2],
+            mapper_2: fields[3],
+            mapper_3: fields[4],
+            tv_system_1: fields[5],
+            prg_ram: fields[6],
+            chr_ram: fields[7],
+            timing: fields[8],
+        };
+
+        if header.prg_banks == 0 {
+            Err(CartridgeError::InvalidHeader("no PRG ROM"))
+        } else if header.is_nes2() && (header.tv_system_1 != 0) {
+            // In NES 2.0 this byte holds the upper bits of the ROM sizes
+            Err(CartridgeError::InvalidHeader(
+                "ROM sizes above 4 MiB are not supported",
+            ))
+        } else {
+            Ok(header)
+        }
     }
-}
 
-pub fn load_cartridge<P: AsRef<Path>>(file: P) -> Option<EmuRef<Cartridge>> {
-    if let Ok(mut reader) = BinReader::from_file(file) {
-        if let Some(header) = INesHeader::from_reader(&mut reader) {
-            // Skip trainer data if it exists
-            if (header.mapper_1 & 0x04) != 0 {
-                reader.skip(512);
+    fn region(&self) -> Region {
+        if self.is_nes2() {
+            match self.timing & 0x03 {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                // Multi-region games run on NTSC
+                _ => Region::Ntsc,
             }
+        } else if (self.tv_system_1 & 0x01) != 0 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        }
+    }
 
-            let mapper_id = (header.mapper_2 & 0xF0) | (header.mapper_1 >> 4);
-            if let Some(mapper) = get_mapper_from_id(mapper_id, header.prg_banks) {
-                let mut prg_mem: Vec<u8> = vec![0; header.prg_banks as usize * PRG_BANK_SIZE];
-                if reader.read_into(&mut prg_mem) != prg_mem.len() {
-                    return None;
-                }
+    #[inline]
+    const fn is_nes2(&self) -> bool {
+        (self.mapper_2 & 0x0C) == 0x08
+    }
 
-                let chr_mem: Vec<u8> = if header.chr_banks == 0 {
-                    // We have RAM instead of ROM
-                    vec![0; CHR_BANK_SIZE]
-                } else {
-                    let mut tmp = vec![0; (header.chr_banks as usize) * CHR_BANK_SIZE];
-                    if reader.read_into(&mut tmp) != tmp.len() {
-                        return None;
-                    }
-                    tmp
-                };
+    #[inline]
+    const fn submapper(&self) -> u8 {
+        if self.is_nes2() {
+            self.mapper_3 >> 4
+        } else {
+            0
+        }
+    }
 
-                let mirror = if (header.mapper_1 & 0x01) != 0 {
-                    MirrorMode::Vertical
-                } else {
-                    MirrorMode::Horizontal
-                };
+    /// Only NES 2.0 headers reliably specify PRG RAM, `None` keeps the board's default
+    fn prg_ram_size(&self) -> Option<usize> {
+        if self.is_nes2() {
+            Some(Self::ram_size(self.prg_ram))
+        } else {
+            None
+        }
+    }
 
-                return Some(make_ref(Cartridge::new(
-                    mapper,
-                    prg_mem,
-                    chr_mem,
-                    header.chr_banks == 0,
-                    mirror,
-                )));
-            }
+    /// `None` keeps the board's default, like for PRG RAM
+    fn chr_ram_size(&self) -> Option<usize> {
+        if self.is_nes2() {
+            // A board without CHR ROM still needs CHR memory, so 0 is treated as unspecified
+            Some(Self::ram_size(self.chr_ram)).filter(|&size| size > 0)
+        } else {
+            None
+        }
+    }
+
+    /// Volatile and battery backed RAM are given as shift counts
+    fn ram_size(sizes: u8) -> usize {
+        let size = |shift: u8| if shift == 0 { 0 } else { 64usize << shift };
+        size(sizes & 0x0F) + size(sizes >> 4)
+    }
+}
+
+#[inline]
+pub fn load_cartridge<P: AsRef<Path>>(file: P) -> Result<EmuRef<Cartridge>, CartridgeError> {
+    load_cartridge_with(file, None, None)
+}
+
+/// Loads a cartridge from a ROM file or a zip or gzip archive containing one
+///
+/// `entry` selects a file inside a zip archive, otherwise the first ROM file is used.
+/// Without an explicit `patch` an IPS, UPS or BPS file next to the ROM is applied if there is one.
+pub fn load_cartridge_with<P: AsRef<Path>>(
+    file: P,
+    entry: Option<&str>,
+    patch: Option<&Path>,
+) -> Result<EmuRef<Cartridge>, CartridgeError> {
+    let mut data = read_file(&file, entry)?;
+
+    let patch_file = patch.map(Path::to_path_buf).or_else(|| find_patch(&file));
+    if let Some(patch_file) = patch_file {
+        let patch_data = std::fs::read(&patch_file)?;
+        data = apply_patch(&data, &patch_data)?;
+    }
+
+    // Battery backed memory is kept in a file next to the ROM
+    parse_cartridge(data, Some(file.as_ref().with_extension("sav")))
+}
+
+/// Loads a cartridge from an iNES image in memory, battery backed memory is not persisted
+#[inline]
+pub fn load_cartridge_from_bytes(data: Vec<u8>) -> Result<EmuRef<Cartridge>, CartridgeError> {
+    parse_cartridge(data, None)
+}
+
+fn parse_cartridge(
+    data: Vec<u8>,
+    save_path: Option<PathBuf>,
+) -> Result<EmuRef<Cartridge>, CartridgeError> {
+    let mut reader = BinReader::new(data);
+    let header = INesHeader::from_reader(&mut reader)?;
+
+    // Skip trainer data if it exists
+    if (header.mapper_1 & 0x04) != 0 {
+        reader.skip(512);
+    }
+
+    let mut prg_mem: Vec<u8> = vec![0; header.prg_banks as usize * PRG_BANK_SIZE];
+    if reader.read_into(&mut prg_mem) != prg_mem.len() {
+        return Err(CartridgeError::TruncatedPrg);
+    }
+
+    let mut chr_mem: Vec<u8> = vec![0; (header.chr_banks as usize) * CHR_BANK_SIZE];
+    if reader.read_into(&mut chr_mem) != chr_mem.len() {
+        return Err(CartridgeError::TruncatedChr);
+    }
+
+    let mut mapper_id = (header.mapper_2 & 0xF0) | (header.mapper_1 >> 4);
+    let mut submapper = header.submapper();
+    let mut battery = (header.mapper_1 & 0x02) != 0;
+    let mut region = header.region();
+    let mut prg_ram_size = header.prg_ram_size();
+    let mut chr_ram_size = header.chr_ram_size();
+
+    let mut mirror = if (header.mapper_1 & 0x08) != 0 {
+        MirrorMode::FourScreen
+    } else if (header.mapper_1 & 0x01) != 0 {
+        MirrorMode::Vertical
+    } else {
+        MirrorMode::Horizontal
+    };
+
+    // Headers of known games are often wrong, the database takes priority
+    let crc = crc32(crc32(0, &prg_mem), &chr_mem);
+    let game = find_game(crc);
+    if let Some(game) = &game {
+        mapper_id = game.mapper_id;
+        if let Some(game_submapper) = game.submapper {
+            submapper = game_submapper;
+        }
+        battery = game.battery;
+        region = game.region;
+        if game.chr_ram_size.is_some() {
+            chr_ram_size = game.chr_ram_size;
+        }
+        if game.prg_ram_size.is_some() {
+            prg_ram_size = game.prg_ram_size;
+        }
+        if let Some(game_mirror) = game.mirror {
+            mirror = game_mirror;
         }
     }
 
-    None
+    let mapper = get_mapper_from_id(mapper_id, submapper, header.prg_banks, header.chr_banks)
+        .ok_or(CartridgeError::UnsupportedMapper(mapper_id))?;
+    if let Some(size) = prg_ram_size {
+        if let Some(prg_ram) = mapper.borrow_mut().prg_ram() {
+            *prg_ram = PrgRam::new(size);
+        }
+    }
+    let chr_ram_size =
+        chr_ram_size.unwrap_or_else(|| mapper.borrow().default_chr_ram_size(chr_mem.len()));
+
+    let mut cartridge = Cartridge::new(
+        mapper,
+        prg_mem,
+        chr_mem,
+        chr_ram_size,
+        mirror,
+        save_path.filter(|_| battery),
+        region,
+    );
+    cartridge.name = game.map(|game| game.name);
+    cartridge.load_save();
+    Ok(make_ref(cartridge))
 }
 
 struct Vram {
@@ -1245,6 +4528,23 @@ impl Vram {
     fn remove_cartridge(&mut self) {
         self.cartridge = None;
     }
+
+    #[inline]
+    fn fill(&mut self, pattern: RamInit, salt: u64) {
+        for (index, table) in self.tables.iter_mut().enumerate() {
+            table.fill(pattern, salt + (index as u64));
+        }
+    }
+
+    #[inline]
+    fn read_ciram(&mut self, index: usize, address: ppu2C02::Address) -> ppu2C02::Word {
+        self.tables[index].read(address & ppu2C02::Address::new(0x03FF))
+    }
+
+    #[inline]
+    fn write_ciram(&mut self, index: usize, address: ppu2C02::Address, data: ppu2C02::Word) {
+        self.tables[index].write(address & ppu2C02::Address::new(0x03FF), data);
+    }
 }
 impl BusComponent<ppu2C02::Address, ppu2C02::Word> for Vram {
     #[inline]
@@ -1258,19 +4558,20 @@ impl BusComponent<ppu2C02::Address, ppu2C02::Word> for Vram {
 
     fn read(&mut self, address: ppu2C02::Address) -> ppu2C02::Word {
         let table_addr = address & ppu2C02::Address::new(0x03FF);
+        let table_index = ((address >> 10u32).0 .0 & 0x0003) as usize;
         if let Some(cartridge) = &self.cartridge {
-            match cartridge.borrow().mirror() {
-                MirrorMode::Horizontal => {
-                    let table_index = (address >> 11u32).0 .0 & 0x0001;
-                    self.tables[table_index as usize].read(table_addr)
+            let cartridge_borrow = cartridge.borrow();
+            let ciram_data = match cartridge_borrow.nametable(table_index) {
+                Nametable::Ciram(index) => self.tables[index].read(table_addr),
+                Nametable::Mapper => Wrapping(0),
+                Nametable::Chr(bank) => {
+                    cartridge_borrow.read_chr(bank * 0x0400 + (table_addr.0 .0 as usize))
                 }
-                MirrorMode::Vertical => {
-                    let table_index = (address >> 10u32).0 .0 & 0x0001;
-                    self.tables[table_index as usize].read(table_addr)
+                Nametable::Cartridge(index) => {
+                    cartridge_borrow.read_nametable_ram(index * 0x0400 + (table_addr.0 .0 as usize))
                 }
-                MirrorMode::OneScreenLow => self.tables[0].read(table_addr),
-                MirrorMode::OneScreenHigh => self.tables[1].read(table_addr),
-            }
+            };
+            cartridge_borrow.nametable_read(address, ciram_data)
         } else {
             Wrapping(0)
         }
@@ -1278,18 +4579,15 @@ impl BusComponent<ppu2C02::Address, ppu2C02::Word> for Vram {
 
     fn write(&mut self, address: ppu2C02::Address, data: ppu2C02::Word) {
         let table_addr = address & ppu2C02::Address::new(0x03FF);
+        let table_index = ((address >> 10u32).0 .0 & 0x0003) as usize;
         if let Some(cartridge) = &self.cartridge {
-            match cartridge.borrow().mirror() {
-                MirrorMode::Horizontal => {
-                    let table_index = (address >> 11u32).0 .0 & 0x0001;
-                    self.tables[table_index as usize].write(table_addr, data);
-                }
-                MirrorMode::Vertical => {
-                    let table_index = (address >> 10u32).0 .0 & 0x0001;
-                    self.tables[table_index as usize].write(table_addr, data);
-                }
-                MirrorMode::OneScreenLow => self.tables[0].write(table_addr, data),
-                MirrorMode::OneScreenHigh => self.tables[1].write(table_addr, data),
+            let cartridge_borrow = cartridge.borrow();
+            match cartridge_borrow.nametable(table_index) {
+                Nametable::Ciram(index) => self.tables[index].write(table_addr, data),
+                Nametable::Mapper => cartridge_borrow.nametable_write(address, data),
+                Nametable::Chr(_) => {}
+                Nametable::Cartridge(index) => cartridge_borrow
+                    .write_nametable_ram(index * 0x0400 + (table_addr.0 .0 as usize), data),
             }
         }
     }
@@ -1337,35 +4635,18 @@ impl BusComponent<cpu6502::Address, cpu6502::Word> for DmaInterface {
     }
 }
 
-bitflags! {
-    pub struct Buttons : u8 {
-        const A      = 0b10000000;
-        const B      = 0b01000000;
-        const SELECT = 0b00100000;
-        const START  = 0b00010000;
-        const UP     = 0b00001000;
-        const DOWN   = 0b00000100;
-        const LEFT   = 0b00000010;
-        const RIGHT  = 0b00000001;
-    }
-}
-
-struct VController {
+struct ControllerPorts<'a> {
     read_range: AddressRange<cpu6502::Address>,
     write_range: AddressRange<cpu6502::Address>,
-    controller: [Wrapping<u8>; 2],
-    buffer: [Buttons; 2],
-    latch: bool,
+    devices: [Option<EmuRef<dyn InputDevice + 'a>>; 3],
 }
-impl VController {
+impl<'a> ControllerPorts<'a> {
     #[inline]
     fn new(start_address: cpu6502::Address) -> Self {
         Self {
             read_range: AddressRange::new(start_address, start_address + Wrapping(1)),
             write_range: AddressRange::new(start_address, start_address),
-            controller: [Wrapping(0); 2],
-            buffer: [Buttons::empty(); 2],
-            latch: false,
+            devices: [None, None, None],
         }
     }
 
@@ -1373,14 +4654,8 @@ impl VController {
     fn create(start_address: cpu6502::Address) -> EmuRef<Self> {
         make_ref(Self::new(start_address))
     }
-
-    #[inline]
-    fn update_state(&mut self, controller_0: Buttons, controller_1: Buttons) {
-        self.buffer[0] = controller_0;
-        self.buffer[1] = controller_1;
-    }
 }
-impl BusComponent<cpu6502::Address, cpu6502::Word> for VController {
+impl<'a> BusComponent<cpu6502::Address, cpu6502::Word> for ControllerPorts<'a> {
     #[inline]
     fn read_range(&self) -> Option<AddressRange<cpu6502::Address>> {
         Some(self.read_range)
@@ -1390,28 +4665,594 @@ impl BusComponent<cpu6502::Address, cpu6502::Word> for VController {
         Some(self.write_range)
     }
 
-    #[inline]
     fn read(&mut self, address: cpu6502::Address) -> cpu6502::Word {
-        // When reading while the controller is latched the bits are refreshed
-        if self.latch {
-            self.controller[address.0 as usize] = Wrapping(self.buffer[address.0 as usize].bits());
+        // $4016 reads port one, $4017 port two, the expansion port sees both
+        let register = address.0 as usize;
+        let mut lines = InputLines::empty();
+        for port in [Port::from_register(register), Port::Expansion].iter() {
+            if let Some(device) = &self.devices[port.index()] {
+                lines |= device.borrow_mut().read(register);
+            }
         }
-
-        // Reading is sequential
-        let result = self.controller[address.0 as usize] >> 7;
-        self.controller[address.0 as usize] <<= 1;
-        result
+        Wrapping(lines.bits())
     }
 
-    #[inline]
     fn write(&mut self, _address: cpu6502::Address, data: cpu6502::Word) {
-        // Cannot write to the controllers, instead this stores the buffer
-        if (data.0 & 0x01) != 0 {
-            self.latch = true;
-        } else if self.latch {
-            self.controller[0] = Wrapping(self.buffer[0].bits());
-            self.controller[1] = Wrapping(self.buffer[1].bits());
-            self.latch = false;
+        // The OUT lines are shared by all ports
+        for device in self.devices.iter().flatten() {
+            device.borrow_mut().write(data.0 & 0x07);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Drives the EEPROM lines of a Bandai board, SCL is bit 5 and SDA bit 6 of $800D
+    struct I2cHost<'m> {
+        mapper: &'m mut BandaiFcg,
+    }
+    impl<'m> I2cHost<'m> {
+        fn set_lines(&mut self, scl: bool, sda: bool) {
+            let data = ((scl as u8) << 5) | ((sda as u8) << 6);
+            self.mapper.cpu_write(Wrapping(0x800D), Wrapping(data));
+        }
+
+        fn sda_in(&mut self) -> bool {
+            match self.mapper.cpu_read(Wrapping(0x6000)) {
+                MapperReadResult::Data(data) => (data.0 & 0x10) != 0,
+                _ => panic!("EEPROM not readable at $6000"),
+            }
+        }
+
+        fn start(&mut self) {
+            self.set_lines(false, true);
+            self.set_lines(true, true);
+            self.set_lines(true, false);
+            self.set_lines(false, false);
+        }
+
+        fn stop(&mut self) {
+            self.set_lines(false, false);
+            self.set_lines(true, false);
+            self.set_lines(true, true);
+        }
+
+        /// Returns whether the EEPROM acknowledged the byte
+        fn send(&mut self, byte: u8) -> bool {
+            for i in (0..8).rev() {
+                let bit = ((byte >> i) & 0x01) != 0;
+                self.set_lines(false, bit);
+                self.set_lines(true, bit);
+                self.set_lines(false, bit);
+            }
+            self.set_lines(false, true);
+            self.set_lines(true, true);
+            let ack = !self.sda_in();
+            self.set_lines(false, true);
+            ack
+        }
+
+        /// Reads a byte and ends the transfer with a NACK
+        fn receive(&mut self) -> u8 {
+            let mut byte = 0;
+            for _ in 0..8 {
+                self.set_lines(false, true);
+                self.set_lines(true, true);
+                byte = (byte << 1) | (self.sda_in() as u8);
+                self.set_lines(false, true);
+            }
+            self.set_lines(false, true);
+            self.set_lines(true, true);
+            self.set_lines(false, true);
+            byte
+        }
+    }
+
+    #[test]
+    fn bandai_eeprom_write_read() {
+        let mut mapper = BandaiFcg::from_id(16, 5, 8);
+        let mut host = I2cHost {
+            mapper: &mut mapper,
+        };
+
+        host.start();
+        assert!(host.send(0xA0));
+        assert!(host.send(0x12));
+        assert!(host.send(0x5A));
+        host.stop();
+
+        host.start();
+        assert!(host.send(0xA0));
+        assert!(host.send(0x12));
+        host.start();
+        assert!(host.send(0xA1));
+        assert_eq!(host.receive(), 0x5A);
+        host.stop();
+
+        assert_eq!(mapper.save_data().unwrap()[0x12], 0x5A);
+    }
+
+    fn prg_addr(mapper: &mut dyn Mapper, addr: u16) -> MapperReadResult {
+        mapper.cpu_read(Wrapping(addr))
+    }
+
+    fn chr_addr(mapper: &mut dyn Mapper, addr: u16) -> MapperReadResult {
+        mapper.ppu_read(ppu2C02::Address::new(addr))
+    }
+
+    #[test]
+    fn color_dreams_bank_select() {
+        // 128k PRG, 64k CHR
+        let mut mapper = ColorDreams::new(8, 8);
+        mapper.cpu_write(Wrapping(0x8000), Wrapping(0x32));
+        assert_eq!(
+            prg_addr(&mut mapper, 0x8123),
+            MapperReadResult::Address(Some(0x10123))
+        );
+        assert_eq!(
+            chr_addr(&mut mapper, 0x0456),
+            MapperReadResult::Address(Some(0x6456))
+        );
+
+        // Banks past the end of the ROM wrap around
+        let mut mapper = ColorDreams::new(4, 2);
+        mapper.cpu_write(Wrapping(0xFFFF), Wrapping(0x73));
+        assert_eq!(
+            prg_addr(&mut mapper, 0xC000),
+            MapperReadResult::Address(Some(0x0C000))
+        );
+        assert_eq!(
+            chr_addr(&mut mapper, 0x1000),
+            MapperReadResult::Address(Some(0x3000))
+        );
+    }
+
+    #[test]
+    fn bnrom_bank_select() {
+        // BNROM has 8k of CHR RAM and a single 32k PRG register
+        let mut mapper = BnRom::new(0, 8, 0);
+        mapper.cpu_write(Wrapping(0x8000), Wrapping(0x02));
+        assert_eq!(
+            prg_addr(&mut mapper, 0x8123),
+            MapperReadResult::Address(Some(0x10123))
+        );
+        assert_eq!(
+            chr_addr(&mut mapper, 0x1234),
+            MapperReadResult::Address(Some(0x1234))
+        );
+
+        // The NINA-001 registers don't exist on BNROM
+        mapper.cpu_write(Wrapping(0x7FFD), Wrapping(0x01));
+        assert_eq!(
+            prg_addr(&mut mapper, 0x8000),
+            MapperReadResult::Address(Some(0x10000))
+        );
+
+        mapper.cpu_write(Wrapping(0xFFFF), Wrapping(0x07));
+        assert_eq!(
+            prg_addr(&mut mapper, 0xFFFF),
+            MapperReadResult::Address(Some(0x1FFFF))
+        );
+    }
+
+    #[test]
+    fn nina001_bank_select() {
+        // NINA-001 is detected by its CHR ROM
+        let mut mapper = BnRom::new(0, 4, 8);
+        mapper.cpu_write(Wrapping(0x7FFD), Wrapping(0x01));
+        mapper.cpu_write(Wrapping(0x7FFE), Wrapping(0x05));
+        mapper.cpu_write(Wrapping(0x7FFF), Wrapping(0x0A));
+        assert_eq!(
+            prg_addr(&mut mapper, 0x8123),
+            MapperReadResult::Address(Some(0x8123))
+        );
+        assert_eq!(
+            chr_addr(&mut mapper, 0x0123),
+            MapperReadResult::Address(Some(0x5123))
+        );
+        assert_eq!(
+            chr_addr(&mut mapper, 0x1123),
+            MapperReadResult::Address(Some(0xA123))
+        );
+
+        // The registers are backed by PRG RAM
+        assert_eq!(
+            prg_addr(&mut mapper, 0x7FFE),
+            MapperReadResult::Data(Wrapping(0x05))
+        );
+
+        // The BNROM register doesn't exist on NINA-001
+        mapper.cpu_write(Wrapping(0x8000), Wrapping(0x00));
+        assert_eq!(
+            prg_addr(&mut mapper, 0x8000),
+            MapperReadResult::Address(Some(0x8000))
+        );
+    }
+
+    #[test]
+    fn camerica_bank_select() {
+        let mut mapper = Camerica::new(0, 8);
+        mapper.cpu_write(Wrapping(0xC000), Wrapping(0x13));
+        assert_eq!(
+            prg_addr(&mut mapper, 0x8123),
+            MapperReadResult::Address(Some(0x0C123))
+        );
+        // The last bank is fixed
+        assert_eq!(
+            prg_addr(&mut mapper, 0xC123),
+            MapperReadResult::Address(Some(0x1C123))
+        );
+
+        // Mirroring is only controlled by Fire Hawk
+        mapper.cpu_write(Wrapping(0x9000), Wrapping(0x10));
+        assert_eq!(mapper.mirror(), None);
+
+        let mut mapper = Camerica::new(1, 8);
+        mapper.cpu_write(Wrapping(0xC000), Wrapping(0x02));
+        mapper.cpu_write(Wrapping(0x9000), Wrapping(0x10));
+        assert_eq!(mapper.mirror(), Some(MirrorMode::OneScreenHigh));
+        mapper.cpu_write(Wrapping(0x9000), Wrapping(0x00));
+        assert_eq!(mapper.mirror(), Some(MirrorMode::OneScreenLow));
+        assert_eq!(
+            prg_addr(&mut mapper, 0x8000),
+            MapperReadResult::Address(Some(0x08000))
+        );
+    }
+
+    #[test]
+    fn nina003_bank_select() {
+        let mut mapper = Nina003::new(4, 8);
+        // Not decoded by the mask
+        mapper.cpu_write(Wrapping(0x4000), Wrapping(0x0F));
+        assert_eq!(
+            chr_addr(&mut mapper, 0x0000),
+            MapperReadResult::Address(Some(0x0000))
+        );
+
+        mapper.cpu_write(Wrapping(0x5F00), Wrapping(0x0D));
+        assert_eq!(
+            prg_addr(&mut mapper, 0x8010),
+            MapperReadResult::Address(Some(0x08010))
+        );
+        assert_eq!(
+            chr_addr(&mut mapper, 0x0010),
+            MapperReadResult::Address(Some(0xA010))
+        );
+
+        let mut mapper = Nina003::new(2, 2);
+        mapper.cpu_write(Wrapping(0x4100), Wrapping(0x0F));
+        assert_eq!(
+            prg_addr(&mut mapper, 0x8000),
+            MapperReadResult::Address(Some(0x0000))
+        );
+        assert_eq!(
+            chr_addr(&mut mapper, 0x0000),
+            MapperReadResult::Address(Some(0x2000))
+        );
+    }
+
+    #[test]
+    fn jaleco_bank_select() {
+        let mut mapper = Jaleco::new(8, 16);
+        // Writes to ROM are ignored
+        mapper.cpu_write(Wrapping(0x8000), Wrapping(0xFF));
+        assert_eq!(
+            prg_addr(&mut mapper, 0x8000),
+            MapperReadResult::Address(Some(0x0000))
+        );
+
+        mapper.cpu_write(Wrapping(0x6000), Wrapping(0x2B));
+        assert_eq!(
+            prg_addr(&mut mapper, 0xFFFF),
+            MapperReadResult::Address(Some(0x17FFF))
+        );
+        assert_eq!(
+            chr_addr(&mut mapper, 0x1FFF),
+            MapperReadResult::Address(Some(0x17FFF))
+        );
+
+        let mut mapper = Jaleco::new(4, 4);
+        mapper.cpu_write(Wrapping(0x7FFF), Wrapping(0x3F));
+        assert_eq!(
+            prg_addr(&mut mapper, 0x8000),
+            MapperReadResult::Address(Some(0x8000))
+        );
+        assert_eq!(
+            chr_addr(&mut mapper, 0x0000),
+            MapperReadResult::Address(Some(0x6000))
+        );
+    }
+
+    #[test]
+    fn unrom_reversed_bank_select() {
+        let mut mapper = UnRomReversed::new(8);
+        mapper.cpu_write(Wrapping(0x8000), Wrapping(0x05));
+        // The first bank is fixed
+        assert_eq!(
+            prg_addr(&mut mapper, 0x8123),
+            MapperReadResult::Address(Some(0x0123))
+        );
+        assert_eq!(
+            prg_addr(&mut mapper, 0xC123),
+            MapperReadResult::Address(Some(0x14123))
+        );
+
+        // Banks past the end of the ROM wrap around
+        mapper.cpu_write(Wrapping(0xFFFF), Wrapping(0x0A));
+        assert_eq!(
+            prg_addr(&mut mapper, 0xC000),
+            MapperReadResult::Address(Some(0x08000))
+        );
+    }
+
+    #[test]
+    fn namco163_reset_keeps_internal_ram() {
+        let mut mapper = Namco163::new(8);
+        // $F800 doubles as the PRG RAM write protection
+        mapper.cpu_write(Wrapping(0xF800), Wrapping(0x40));
+        mapper.cpu_write(Wrapping(0x6000), Wrapping(0x56));
+        // Address 0 with auto increment
+        mapper.cpu_write(Wrapping(0xF800), Wrapping(0x80));
+        mapper.cpu_write(Wrapping(0x4800), Wrapping(0x12));
+        mapper.cpu_write(Wrapping(0x4800), Wrapping(0x34));
+
+        mapper.reset();
+        mapper.cpu_write(Wrapping(0xF800), Wrapping(0x01));
+        assert_eq!(
+            prg_addr(&mut mapper, 0x4800),
+            MapperReadResult::Data(Wrapping(0x34))
+        );
+
+        let data = mapper.save_data().unwrap();
+        assert_eq!(data.len(), 0x2000 + 0x80);
+        assert_eq!(data[0x0000], 0x56);
+        assert_eq!(data[0x2000], 0x12);
+
+        let mut mapper = Namco163::new(8);
+        mapper.load_save_data(&data);
+        mapper.cpu_write(Wrapping(0xF800), Wrapping(0x00));
+        assert_eq!(
+            prg_addr(&mut mapper, 0x4800),
+            MapperReadResult::Data(Wrapping(0x12))
+        );
+        assert_eq!(
+            prg_addr(&mut mapper, 0x6000),
+            MapperReadResult::Data(Wrapping(0x56))
+        );
+    }
+
+    /// iNES image with 32k of PRG ROM, `flags_7` selects NES 2.0 with $08
+    fn rom_image(mapper_id: u8, chr_banks: u8, flags_7: u8, chr_ram: u8) -> Vec<u8> {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 2, chr_banks, mapper_id << 4];
+        data.extend_from_slice(&[(mapper_id & 0xF0) | flags_7, 0, 0, 0, chr_ram, 0, 0, 0, 0]);
+        let rom_size = 2 * PRG_BANK_SIZE + (chr_banks as usize) * CHR_BANK_SIZE;
+        data.resize(data.len() + rom_size, 0);
+        data
+    }
+
+    fn chr_ram_size(data: Vec<u8>) -> usize {
+        let cartridge = load_cartridge_from_bytes(data).unwrap();
+        let cartridge = cartridge.borrow();
+        let size = cartridge.ppu_adapter.borrow().chr_ram.len();
+        size
+    }
+
+    #[test]
+    fn chr_ram_size_from_header_or_board() {
+        assert_eq!(chr_ram_size(rom_image(0, 0, 0x00, 0x00)), 0x2000);
+        assert_eq!(chr_ram_size(rom_image(4, 1, 0x00, 0x00)), 0);
+        // TQROM has CHR RAM next to CHR ROM
+        assert_eq!(chr_ram_size(rom_image(119, 1, 0x00, 0x00)), 0x2000);
+
+        // NES 2.0 gives the size as a shift count
+        assert_eq!(chr_ram_size(rom_image(0, 0, 0x08, 0x09)), 0x8000);
+        assert_eq!(chr_ram_size(rom_image(4, 1, 0x08, 0x07)), 0x2000);
+        assert_eq!(chr_ram_size(rom_image(119, 1, 0x08, 0x00)), 0x2000);
+    }
+
+    #[test]
+    fn memory_snapshot_follows_mapped_prg_ram() {
+        let mut nes = nes_with_program(&[]);
+        nes.set_cartridge(load_cartridge_from_bytes(rom_image(0, 1, 0x00, 0x00)).unwrap());
+        assert_eq!(nes.memory_snapshot().read(0x6000), None);
+
+        nes.set_cartridge(load_cartridge_from_bytes(rom_image(5, 1, 0x00, 0x00)).unwrap());
+        {
+            let cpu_bus = nes.cpu_bus.borrow();
+            // Unlock PRG RAM and write to the second bank
+            cpu_bus.write(Wrapping(0x5102), Wrapping(0x02));
+            cpu_bus.write(Wrapping(0x5103), Wrapping(0x01));
+            cpu_bus.write(Wrapping(0x5113), Wrapping(0x01));
+            cpu_bus.write(Wrapping(0x6000), Wrapping(0x42));
+        }
+        assert_eq!(nes.memory_snapshot().read(0x6000), Some(0x42));
+
+        nes.cpu_bus.borrow().write(Wrapping(0x5113), Wrapping(0x00));
+        assert_eq!(nes.memory_snapshot().read(0x6000), Some(0x00));
+    }
+
+    /// Without a cartridge the reset vector reads as 0, so programs run from RAM
+    fn nes_with_program(program: &[u8]) -> Nes<'static> {
+        let mut nes = Nes::new();
+        {
+            let cpu_bus = nes.cpu_bus.borrow();
+            for (i, &data) in program.iter().enumerate() {
+                cpu_bus.write(Wrapping(i as u16), Wrapping(data));
+            }
+        }
+        nes.reset();
+        nes
+    }
+
+    #[test]
+    fn masked_irq_does_not_stall_cpu() {
+        #[rustfmt::skip]
+        let mut nes = nes_with_program(&[
+            0x78,             // SEI
+            0xA9, 0x8F,       // LDA #$8F
+            0x8D, 0x10, 0x40, // STA $4010 (DMC IRQ enabled, fastest rate)
+            0xA9, 0x00,       // LDA #$00
+            0x8D, 0x13, 0x40, // STA $4013 (sample length 1)
+            0xA9, 0x10,       // LDA #$10
+            0x8D, 0x15, 0x40, // STA $4015 (start DMC)
+            0xE6, 0x20,       // INC $20
+            0x4C, 0x10, 0x00, // JMP $0010
+        ]);
+
+        let mut buffer = SampleBuffer::new(4096);
+        for _ in 0..1000 {
+            nes.next_instruction(&mut buffer);
+            buffer.clear();
+        }
+        // Nothing acknowledges the DMC IRQ, so it stays asserted
+        assert!(nes.apu.borrow().dmc_irq_requested());
+
+        let counter = nes.cpu_bus.borrow().read(Wrapping(0x0020));
+        for _ in 0..10 {
+            nes.next_instruction(&mut buffer);
         }
+        assert_ne!(nes.cpu_bus.borrow().read(Wrapping(0x0020)), counter);
+    }
+
+    /// Starts the DMC at its slowest rate with the given length register and fetches the first byte
+    fn start_dmc(nes: &mut Nes, length: u8, buffer: &mut SampleBuffer) {
+        {
+            let cpu_bus = nes.cpu_bus.borrow();
+            cpu_bus.write(Wrapping(0x4010), Wrapping(0x00));
+            cpu_bus.write(Wrapping(0x4013), Wrapping(length));
+            cpu_bus.write(Wrapping(0x4015), Wrapping(0x10));
+        }
+        assert!(nes.apu.borrow().dmc_dma_pending());
+        nes.run_dmc_dma(None, buffer);
+    }
+
+    /// Clocks single cycles until the DMC requests its next byte and returns that cycle
+    fn wait_for_dmc_request(nes: &mut Nes, buffer: &mut SampleBuffer) -> u64 {
+        while !nes.apu.borrow().dmc_dma_pending() {
+            nes.clock_components(1, buffer);
+        }
+        nes.cpu_cycle
+    }
+
+    #[test]
+    fn dmc_dma_cycles() {
+        let mut buffer = SampleBuffer::new(4096);
+        for (parity, expected) in [(0, 3), (1, 4)].iter() {
+            let mut nes = nes_with_program(&[]);
+            start_dmc(&mut nes, 0x01, &mut buffer);
+            wait_for_dmc_request(&mut nes, &mut buffer);
+            if (nes.cpu_cycle % 2) != *parity {
+                // Delaying the DMA by one cycle does not change when the request happened
+                nes.clock_components(1, &mut buffer);
+            }
+
+            let start = nes.cpu_cycle;
+            nes.run_dmc_dma(None, &mut buffer);
+            assert_eq!(nes.cpu_cycle - start, *expected);
+            assert!(!nes.apu.borrow().dmc_dma_pending());
+        }
+    }
+
+    #[test]
+    fn dmc_dma_during_oam_dma() {
+        let mut buffer = SampleBuffer::new(4096);
+
+        // Two requests in a row give the request period
+        let mut nes = nes_with_program(&[]);
+        start_dmc(&mut nes, 0x01, &mut buffer);
+        let first_request = wait_for_dmc_request(&mut nes, &mut buffer);
+        nes.run_dmc_dma(None, &mut buffer);
+        let second_request = wait_for_dmc_request(&mut nes, &mut buffer);
+
+        // Starts the OAM DMA earlier and earlier before the second request
+        let mut extra_cycles = Vec::new();
+        for offset in 0..600 {
+            let mut nes = nes_with_program(&[]);
+            start_dmc(&mut nes, 0x01, &mut buffer);
+            nes.clock_components((first_request - nes.cpu_cycle) as u32, &mut buffer);
+            assert!(nes.apu.borrow().dmc_dma_pending());
+            nes.run_dmc_dma(None, &mut buffer);
+            let start = second_request - offset;
+            nes.clock_components((start - nes.cpu_cycle) as u32, &mut buffer);
+            buffer.clear();
+
+            nes.run_oam_dma(0x02, &mut buffer);
+            let base = 513 + ((start + 1) % 2);
+            extra_cycles.push(nes.cpu_cycle - start - base);
+        }
+
+        // A request at the start of the transfer takes a get cycle and wastes the following put,
+        // one at the tail only adds its get cycle and one after the transfer adds nothing
+        assert_eq!(extra_cycles[0], 2);
+        assert!(extra_cycles.contains(&1));
+        assert_eq!(extra_cycles[599], 0);
+        assert!(extra_cycles.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+
+    /// Counts the accesses to a single address
+    struct AccessCounter {
+        reads: u32,
+        writes: u32,
+    }
+    impl BusComponent<cpu6502::Address, cpu6502::Word> for AccessCounter {
+        fn read_range(&self) -> Option<AddressRange<cpu6502::Address>> {
+            Some(AddressRange::new(Wrapping(0x5000), Wrapping(0x5000)))
+        }
+        fn write_range(&self) -> Option<AddressRange<cpu6502::Address>> {
+            Some(AddressRange::new(Wrapping(0x5000), Wrapping(0x5000)))
+        }
+
+        fn read(&mut self, _address: cpu6502::Address) -> cpu6502::Word {
+            self.reads += 1;
+            Wrapping(0)
+        }
+        fn write(&mut self, _address: cpu6502::Address, _data: cpu6502::Word) {
+            self.writes += 1;
+        }
+    }
+
+    #[test]
+    fn dmc_dma_repeats_halted_read() {
+        let mut buffer = SampleBuffer::new(4096);
+        let mut extra_reads = Vec::new();
+        for delay in 0..11 {
+            #[rustfmt::skip]
+            let mut nes = nes_with_program(&[
+                0xAD, 0x00, 0x50, // LDA $5000
+                0x8D, 0x00, 0x50, // STA $5000
+                0x4C, 0x00, 0x00, // JMP $0000
+            ]);
+            let counter = make_ref(AccessCounter {
+                reads: 0,
+                writes: 0,
+            });
+            nes.cpu_bus.borrow_mut().add_component(counter.clone());
+
+            // Only one more byte is requested, once the output unit takes the buffered one
+            start_dmc(&mut nes, 0x00, &mut buffer);
+            nes.cpu_bus.borrow().write(Wrapping(0x4015), Wrapping(0x10));
+            nes.clock_components(delay, &mut buffer);
+
+            let end = nes.cpu_cycle + 8000;
+            loop {
+                let writes = counter.borrow().writes;
+                nes.next_instruction(&mut buffer);
+                buffer.clear();
+                if (nes.cpu_cycle > end) && (counter.borrow().writes != writes) {
+                    break;
+                }
+            }
+            assert!(!nes.apu.borrow().dmc_dma_pending());
+
+            let counter = counter.borrow();
+            extra_reads.push(counter.reads - counter.writes);
+        }
+
+        // The request lands on every cycle of the 11 cycle loop once,
+        // only the halt on the final cycle of LDA repeats the read
+        assert!(extra_reads.iter().all(|&extra| extra <= 1));
+        assert_eq!(extra_reads.iter().sum::<u32>(), 1);
     }
 }
@@ -0,0 +1,106 @@
+use crate::system::nes::{MirrorMode, Region};
+
+const DATABASE: &str = include_str!("../../res/nesdb.txt");
+
+/// Board information for a known game, overrides what the iNES header says
+pub struct GameInfo {
+    pub name: String,
+    pub mapper_id: u8,
+    /// `None` if the header is trusted, most sources don't know about submappers
+    pub submapper: Option<u8>,
+    /// `None` if the header is trusted, for example because the mapper controls mirroring
+    pub mirror: Option<MirrorMode>,
+    pub battery: bool,
+    /// `None` if the header is trusted
+    pub prg_ram_size: Option<usize>,
+    /// `None` if the header or the board decides, boards with CHR ROM usually have none
+    pub chr_ram_size: Option<usize>,
+    pub region: Region,
+}
+impl GameInfo {
+    fn parse(fields: &[&str]) -> Option<Self> {
+        if fields.len() != 9 {
+            return None;
+        }
+
+        let mirror = match fields[3] {
+            "H" => Some(MirrorMode::Horizontal),
+            "V" => Some(MirrorMode::Vertical),
+            "4" => Some(MirrorMode::FourScreen),
+            _ => None,
+        };
+        let region = match fields[7] {
+            "PAL" => Region::Pal,
+            "Dendy" => Region::Dendy,
+            _ => Region::Ntsc,
+        };
+
+        Some(Self {
+            name: fields[8].to_string(),
+            mapper_id: fields[1].parse().ok()?,
+            submapper: match fields[2] {
+                "-" => None,
+                submapper => Some(submapper.parse().ok()?),
+            },
+            mirror,
+            battery: fields[4] == "1",
+            prg_ram_size: match fields[5] {
+                "-" => None,
+                size => Some(size.parse::<usize>().ok()? * 0x0400),
+            },
+            chr_ram_size: match fields[6].parse::<usize>().ok()? {
+                0 => None,
+                size => Some(size * 0x0400),
+            },
+            region,
+        })
+    }
+}
+
+/// Looks up a game by the CRC32 of its PRG and CHR ROM
+pub fn find_game(crc: u32) -> Option<GameInfo> {
+    for line in DATABASE.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(';').collect();
+        if u32::from_str_radix(fields[0], 16) == Ok(crc) {
+            return GameInfo::parse(&fields);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_line(line: &str) -> Option<GameInfo> {
+        GameInfo::parse(&line.split(';').collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn unknown_fields_keep_the_header() {
+        let game = find_game(0x3337EC46).unwrap();
+        assert_eq!(game.mapper_id, 0);
+        assert_eq!(game.submapper, None);
+        assert_eq!(game.chr_ram_size, None);
+        assert_eq!(game.prg_ram_size, Some(0));
+    }
+
+    #[test]
+    fn known_fields_override_the_header() {
+        let game = parse_line("00000000;4;4;-;1;8;0;NTSC;MMC3 rev A").unwrap();
+        assert_eq!(game.submapper, Some(4));
+        assert!(game.mirror.is_none());
+        assert!(game.battery);
+
+        let game = parse_line("00000000;119;-;V;0;-;8;NTSC;TQROM").unwrap();
+        assert_eq!(game.submapper, None);
+        assert_eq!(game.prg_ram_size, None);
+        assert_eq!(game.chr_ram_size, Some(0x2000));
+    }
+}
@@ -0,0 +1,263 @@
+use crate::system::cheats::{Cheat, CheatEffect};
+
+/// CPU address ranges that are searched, internal RAM and cartridge PRG RAM
+const REGIONS: [(u16, usize); 2] = [(0x0000, 0x0800), (0x6000, 0x2000)];
+
+/// The searchable memory at one point in time
+#[derive(Clone, Debug)]
+pub struct MemorySnapshot {
+    data: Vec<u8>,
+    /// Regions without RAM in them are not searched
+    mapped: [bool; 2],
+}
+impl MemorySnapshot {
+    /// `prg_ram` is the content of $6000-$7FFF, `None` if no RAM is mapped there
+    pub fn new(ram: &[u8], prg_ram: Option<&[u8]>) -> Self {
+        let mut data = Vec::with_capacity(REGIONS[0].1 + REGIONS[1].1);
+        let mut mapped = [false; 2];
+        for (i, (region, bytes)) in REGIONS.iter().zip([Some(ram), prg_ram].iter()).enumerate() {
+            let start = data.len();
+            if let Some(bytes) = bytes {
+                data.extend(bytes.iter().take(region.1));
+                mapped[i] = bytes.len() >= region.1;
+            }
+            data.resize(start + region.1, 0);
+        }
+
+        Self { data, mapped }
+    }
+
+    fn index(&self, address: u16) -> Option<usize> {
+        let mut offset = 0;
+        for (&(start, size), &mapped) in REGIONS.iter().zip(self.mapped.iter()) {
+            if (address >= start) && (((address - start) as usize) < size) {
+                return Some(offset + (address - start) as usize).filter(|_| mapped);
+            }
+            offset += size;
+        }
+
+        None
+    }
+
+    #[inline]
+    pub fn read(&self, address: u16) -> Option<u8> {
+        self.index(address).map(|index| self.data[index])
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ValueType {
+    U8,
+    I8,
+    /// Little endian, like the 6502 stores addresses
+    U16,
+    I16,
+}
+impl ValueType {
+    #[inline]
+    pub const fn size(self) -> usize {
+        match self {
+            ValueType::U8 | ValueType::I8 => 1,
+            ValueType::U16 | ValueType::I16 => 2,
+        }
+    }
+
+    /// Reads a value from a snapshot, both bytes of a 16 bit value have to be in the same region
+    fn read(self, snapshot: &MemorySnapshot, address: u16) -> Option<i32> {
+        let lo = snapshot.read(address)?;
+        match self {
+            ValueType::U8 => Some(lo as i32),
+            ValueType::I8 => Some((lo as i8) as i32),
+            ValueType::U16 | ValueType::I16 => {
+                let index = snapshot.index(address)?;
+                if snapshot.index(address.checked_add(1)?) != Some(index + 1) {
+                    return None;
+                }
+
+                let value = u16::from_le_bytes([lo, snapshot.data[index + 1]]);
+                if self == ValueType::U16 {
+                    Some(value as i32)
+                } else {
+                    Some((value as i16) as i32)
+                }
+            }
+        }
+    }
+}
+
+/// How the value in the new snapshot has to relate to the previous one
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SearchFilter {
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+    IncreasedBy(i32),
+    DecreasedBy(i32),
+    /// The new value equals a specific value
+    Equals(i32),
+    LessThan(i32),
+    GreaterThan(i32),
+}
+impl SearchFilter {
+    fn matches(self, previous: i32, current: i32) -> bool {
+        match self {
+            SearchFilter::Unchanged => current == previous,
+            SearchFilter::Changed => current != previous,
+            SearchFilter::Increased => current > previous,
+            SearchFilter::Decreased => current < previous,
+            SearchFilter::IncreasedBy(n) => current == previous + n,
+            SearchFilter::DecreasedBy(n) => current == previous - n,
+            SearchFilter::Equals(n) => current == n,
+            SearchFilter::LessThan(n) => current < n,
+            SearchFilter::GreaterThan(n) => current > n,
+        }
+    }
+}
+
+/// Narrows down the addresses a game stores a value at by comparing snapshots
+pub struct RamSearch {
+    value_type: ValueType,
+    snapshot: MemorySnapshot,
+    candidates: Vec<u16>,
+}
+impl RamSearch {
+    /// Starts a new search, every address is a candidate at first
+    pub fn new(snapshot: MemorySnapshot, value_type: ValueType) -> Self {
+        let candidates = REGIONS
+            .iter()
+            .flat_map(|&(start, size)| (0..size).map(move |offset| start + (offset as u16)))
+            .filter(|&address| value_type.read(&snapshot, address).is_some())
+            .collect();
+
+        Self {
+            value_type,
+            snapshot,
+            candidates,
+        }
+    }
+
+    #[inline]
+    pub const fn value_type(&self) -> ValueType {
+        self.value_type
+    }
+
+    #[inline]
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    /// The value at an address in the most recent snapshot
+    #[inline]
+    pub fn value(&self, address: u16) -> Option<i32> {
+        self.value_type.read(&self.snapshot, address)
+    }
+
+    /// Removes all candidates that don't match the filter, the snapshot becomes the new reference
+    pub fn filter(&mut self, snapshot: MemorySnapshot, filter: SearchFilter) {
+        let value_type = self.value_type;
+        let previous = &self.snapshot;
+        self.candidates.retain(|&address| {
+            match (
+                value_type.read(previous, address),
+                value_type.read(&snapshot, address),
+            ) {
+                (Some(previous), Some(current)) => filter.matches(previous, current),
+                _ => false,
+            }
+        });
+        self.snapshot = snapshot;
+    }
+
+    /// Creates cheats that keep the address at its current value
+    pub fn freeze(&self, address: u16, description: &str) -> Vec<Cheat> {
+        self.freeze_at(address, self.value(address).unwrap_or(0), description)
+    }
+
+    /// Creates cheats that keep the address at a value
+    pub fn freeze_at(&self, address: u16, value: i32, description: &str) -> Vec<Cheat> {
+        let bytes = (value as u16).to_le_bytes();
+        (0..self.value_type.size())
+            .map(|i| {
+                let address = address.wrapping_add(i as u16);
+                let code = format!("{:0>4X}:{:0>2X}", address, bytes[i]);
+                let effect = CheatEffect::Write {
+                    address,
+                    value: bytes[i],
+                };
+                Cheat::from_effect(&code, description, effect)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A snapshot with PRG RAM that is zero except for the given bytes
+    fn snapshot(bytes: &[(u16, u8)]) -> MemorySnapshot {
+        let mut ram = vec![0; REGIONS[0].1];
+        let mut prg_ram = vec![0; REGIONS[1].1];
+        for &(address, value) in bytes.iter() {
+            if address < 0x6000 {
+                ram[address as usize] = value;
+            } else {
+                prg_ram[(address - 0x6000) as usize] = value;
+            }
+        }
+        MemorySnapshot::new(&ram, Some(&prg_ram))
+    }
+
+    #[test]
+    fn signed_bytes() {
+        let mut search = RamSearch::new(snapshot(&[(0x0010, 0xFF)]), ValueType::I8);
+        assert_eq!(search.value(0x0010), Some(-1));
+
+        // -1 to 1 is an increase for signed values only
+        search.filter(snapshot(&[(0x0010, 0x01)]), SearchFilter::IncreasedBy(2));
+        assert_eq!(search.candidates(), &[0x0010]);
+
+        let mut search = RamSearch::new(snapshot(&[(0x0010, 0xFF)]), ValueType::U8);
+        search.filter(snapshot(&[(0x0010, 0x01)]), SearchFilter::Decreased);
+        assert_eq!(search.candidates(), &[0x0010]);
+    }
+
+    #[test]
+    fn words() {
+        let mut search = RamSearch::new(snapshot(&[(0x6100, 0xFF)]), ValueType::U16);
+        // The last byte of each region can't start a word
+        assert!(!search.candidates().contains(&0x07FF));
+        assert!(!search.candidates().contains(&0x7FFF));
+
+        // $6100 goes from $00FF to $0100 and $6101 from 0 to 1, $60FF drops from $FF00 to 0
+        search.filter(snapshot(&[(0x6101, 0x01)]), SearchFilter::IncreasedBy(1));
+        assert_eq!(search.candidates(), &[0x6100, 0x6101]);
+        search.filter(snapshot(&[(0x6101, 0x01)]), SearchFilter::Equals(0x0100));
+        assert_eq!(search.candidates(), &[0x6100]);
+        assert_eq!(search.value(0x6100), Some(0x0100));
+
+        let mut search = RamSearch::new(snapshot(&[]), ValueType::I16);
+        search.filter(
+            snapshot(&[(0x0020, 0xFE), (0x0021, 0xFF)]),
+            SearchFilter::DecreasedBy(2),
+        );
+        assert_eq!(search.candidates(), &[0x0020]);
+        assert_eq!(search.value(0x0020), Some(-2));
+    }
+
+    #[test]
+    fn unmapped_prg_ram() {
+        let search = RamSearch::new(MemorySnapshot::new(&[0; 0x0800], None), ValueType::U8);
+        assert_eq!(search.candidates().len(), 0x0800);
+        assert_eq!(search.value(0x6000), None);
+    }
+
+    #[test]
+    fn freeze_words() {
+        let search = RamSearch::new(snapshot(&[(0x0030, 0x34), (0x0031, 0x12)]), ValueType::U16);
+        let cheats = search.freeze(0x0030, "");
+        let codes: Vec<&str> = cheats.iter().map(|cheat| cheat.code()).collect();
+        assert_eq!(codes, ["0030:34", "0031:12"]);
+    }
+}
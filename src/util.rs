@@ -8,7 +8,8 @@ pub struct BinReader {
     pos: usize,
 }
 impl BinReader {
-    const fn new(data: Vec<u8>) -> Self {
+    /// Reads from data that is already in memory
+    pub const fn new(data: Vec<u8>) -> Self {
         Self { data, pos: 0 }
     }
 
@@ -41,6 +42,22 @@ impl BinReader {
     }
 }
 
+/// CRC-32 as used by zip files and ROM databases, `crc` is the result of a previous call or 0
+pub fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data.iter() {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if (crc & 0x01) != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
 pub fn pixels_to_data(pixels: &[Color]) -> &[u8] {
     const COLOR_SIZE: usize = std::mem::size_of::<Color>();
 
@@ -1,5 +1,7 @@
 use crate::bus::*;
-use crate::system::nes::Cartridge;
+use crate::memory::RamInit;
+use crate::system::input::LightSource;
+use crate::system::nes::{Cartridge, PpuFetch, Region};
 use crate::types::*;
 use crate::video::*;
 use std::num::Wrapping;
@@ -21,9 +23,15 @@ pub const SCREEN_WIDTH: usize = 256;
 pub const SCREEN_HEIGHT: usize = 240;
 
 const MAX_CYCLE: u16 = 340;
-const MAX_SCANLINE: i16 = 260;
+const MAX_SCANLINE_NTSC: i16 = 260;
+const MAX_SCANLINE_PAL: i16 = 310;
 const HBLANK_CYCLE: u16 = 256;
+// Sprites for the next line are evaluated after the last visible pixel, then fetched until dot 320
+const SPRITE_EVALUATION_CYCLE: u16 = 258;
+const SPRITE_FETCH_END_CYCLE: u16 = 320;
 const VBLANK_LINE: i16 = 240;
+// The Dendy starts vertical blanking 51 lines after the last visible line
+const VBLANK_LINE_DENDY: i16 = 290;
 
 // Helper function to keep some code below clean
 #[inline]
@@ -121,6 +129,11 @@ impl PixelBuffer {
     }
 }
 impl PixelBuffer {
+    #[inline]
+    fn get_pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[(y * SCREEN_WIDTH) + x]
+    }
+
     #[inline]
     fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
         let index = (y * SCREEN_WIDTH) + x;
@@ -251,6 +264,14 @@ impl ObjectAttributeMemory {
         let offset = (addr.0 as usize) - (index * 4);
         self.entries[index].attribs[offset] = data;
     }
+
+    fn fill(&mut self, pattern: RamInit, salt: u64) {
+        for (index, entry) in self.entries.iter_mut().enumerate() {
+            for (offset, attrib) in entry.attribs.iter_mut().enumerate() {
+                *attrib = Wrapping(pattern.value(index * 4 + offset, salt));
+            }
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -312,10 +333,12 @@ pub struct Ppu2C02<'a> {
     bus: EmuRef<Bus<'a, Address, Word>>,
     range: AddressRange<cpu::cpu6502::Address>,
     cartridge: Option<EmuRef<Cartridge>>,
+    region: Region,
 
     oam: ObjectAttributeMemory,
     scanline: i16,
     cycle: u16,
+    total_cycles: u64,
     back_buffer: Box<PixelBuffer>,
     front_buffer: Box<PixelBuffer>,
     control: PpuControl,
@@ -350,9 +373,11 @@ impl<'a> Ppu2C02<'a> {
             bus,
             range: AddressRange::new(range_start, range_start + ADDR_MAX),
             cartridge: None,
+            region: Region::Ntsc,
             oam,
             scanline: 0,
             cycle: 0,
+            total_cycles: 0,
             back_buffer: Box::new(PixelBuffer::new()),
             front_buffer: Box::new(PixelBuffer::new()),
             control: PpuControl::empty(),
@@ -404,6 +429,36 @@ impl<'a> Ppu2C02<'a> {
         self.cartridge = None;
     }
 
+    fn set_fetch(&self, fetch: PpuFetch) {
+        if let Some(cartridge) = &self.cartridge {
+            cartridge.borrow_mut().set_ppu_fetch(fetch);
+        }
+    }
+
+    /// Lets the cartridge watch the PPU address bus
+    fn notify_address(&self, addr: Address) {
+        if let Some(cartridge) = &self.cartridge {
+            cartridge
+                .borrow_mut()
+                .on_ppu_address(addr, self.total_cycles);
+        }
+    }
+
+    #[inline]
+    fn rendering_enabled(&self) -> bool {
+        self.mask
+            .intersects(PpuMask::RENDER_BACKGROUND | PpuMask::RENDER_SPRITES)
+    }
+
+    /// Reads from the bus while rendering
+    fn fetch(&self, addr: Address) -> Word {
+        // The PPU doesn't access memory at all while rendering is disabled
+        if self.rendering_enabled() {
+            self.notify_address(addr);
+        }
+        self.read_bus(addr)
+    }
+
     fn read_bus(&self, mut addr: Address) -> Word {
         if addr >= 0x3F00 {
             addr &= 0x001F;
@@ -534,12 +589,12 @@ impl<'a> Ppu2C02<'a> {
             0 => {
                 self.load_shifters();
                 self.bg_next_id = self
-                    .read_bus(Address::new(0x2000 | (self.vram_addr.value & 0x0FFF)))
+                    .fetch(Address::new(0x2000 | (self.vram_addr.value & 0x0FFF)))
                     .0;
             }
             2 => {
                 self.bg_next_attr = self
-                    .read_bus(Address::new(
+                    .fetch(Address::new(
                         0x23C0
                             | (self.vram_addr.nametable_y << 11)
                             | (self.vram_addr.nametable_x << 10)
@@ -559,13 +614,13 @@ impl<'a> Ppu2C02<'a> {
                 let bg_table = self.control.contains(PpuControl::PATTERN_BACKGROUND);
                 let offset = select(bg_table, 1 << 12, 0);
                 let addr = offset + ((self.bg_next_id as u16) << 4) + self.vram_addr.fine_y;
-                self.bg_next_lsb = self.read_bus(Address::new(addr)).0;
+                self.bg_next_lsb = self.fetch(Address::new(addr)).0;
             }
             6 => {
                 let bg_table = self.control.contains(PpuControl::PATTERN_BACKGROUND);
                 let offset = select(bg_table, 1 << 12, 0);
                 let addr = offset + ((self.bg_next_id as u16) << 4) + self.vram_addr.fine_y + 8;
-                self.bg_next_msb = self.read_bus(Address::new(addr)).0;
+                self.bg_next_msb = self.fetch(Address::new(addr)).0;
             }
             7 => self.inc_x(),
             _ => {}
@@ -611,63 +666,125 @@ impl<'a> Ppu2C02<'a> {
         }
     }
 
-    fn load_foreground_data(&mut self) {
-        if (self.cycle == MAX_CYCLE) && (self.scanline >= 0) {
-            // Clear sprites
-            self.sprites_line = [ObjectAttributes::new(); 8];
-            for i in 0..8 {
-                self.sprite_pattern_lo[i] = 0;
-                self.sprite_pattern_hi[i] = 0;
-            }
-
-            let sprite_height = select(self.control.contains(PpuControl::SPRITE_SIZE), 16, 8);
-
-            self.sprite_count = 0;
-            let mut oam_index: usize = 0;
-            self.allow_zero_hit = false;
-            while (oam_index < 64) && (self.sprite_count < 9) {
-                let sprite = self.oam.get(oam_index);
-
-                let diff = self.scanline - (sprite.y() as i16);
-                if (diff >= 0) && (diff < sprite_height) {
-                    if self.sprite_count < 8 {
-                        if oam_index == 0 {
-                            // Sprite zero hit detection
-                            self.allow_zero_hit = true;
-                        }
+    /// Address of the pattern unused sprite slots fetch, they use tile $FF
+    fn dummy_sprite_addr(&self) -> u16 {
+        if self.control.contains(PpuControl::SPRITE_SIZE) {
+            0x1FF0
+        } else {
+            select(
+                self.control.contains(PpuControl::PATTERN_SPRITE),
+                1 << 12,
+                0,
+            ) | 0x0FF0
+        }
+    }
 
-                        self.sprites_line[self.sprite_count] = sprite;
-                        self.sprite_count += 1;
-                    } else {
-                        self.status.insert(PpuStatus::SPRITE_OVERFLOW);
+    /// Finds the sprites that are visible on the next scanline
+    fn evaluate_sprites(&mut self) {
+        // Clear sprites
+        self.sprites_line = [ObjectAttributes::new(); 8];
+        for i in 0..8 {
+            self.sprite_pattern_lo[i] = 0;
+            self.sprite_pattern_hi[i] = 0;
+        }
+
+        let sprite_height = select(self.control.contains(PpuControl::SPRITE_SIZE), 16, 8);
+
+        self.sprite_count = 0;
+        let mut oam_index: usize = 0;
+        self.allow_zero_hit = false;
+        while (oam_index < 64) && (self.sprite_count < 9) {
+            let sprite = self.oam.get(oam_index);
+
+            let diff = self.scanline - (sprite.y() as i16);
+            if (diff >= 0) && (diff < sprite_height) {
+                if self.sprite_count < 8 {
+                    if oam_index == 0 {
+                        // Sprite zero hit detection
+                        self.allow_zero_hit = true;
                     }
+
+                    self.sprites_line[self.sprite_count] = sprite;
+                    self.sprite_count += 1;
+                } else {
+                    self.status.insert(PpuStatus::SPRITE_OVERFLOW);
                 }
+            }
+
+            oam_index += 1;
+        }
+    }
 
-                oam_index += 1;
+    /// Fetches the low or high pattern byte of a sprite slot
+    fn fetch_sprite_pattern(&mut self, index: usize, high: bool) {
+        let offset = select(high, 8, 0);
+
+        self.set_fetch(PpuFetch::Sprite);
+        if index < self.sprite_count {
+            let sprite = self.sprites_line[index];
+            let addr = self.get_sprite_addr(&sprite) + offset;
+            let mut pattern = self.fetch(Address::new(addr)).0;
+            if sprite.attr().contains(SpriteAttributes::FLIP_HOR) {
+                pattern = flip_byte(pattern);
             }
 
-            for i in 0..self.sprite_count {
-                let sprite = &self.sprites_line[i];
-                let addr_lo = self.get_sprite_addr(sprite);
-                let addr_hi = addr_lo + 8;
-
-                let mut pattern_lo = self.read_bus(Address::new(addr_lo)).0;
-                let mut pattern_hi = self.read_bus(Address::new(addr_hi)).0;
-                if sprite.attr().contains(SpriteAttributes::FLIP_HOR) {
-                    pattern_lo = flip_byte(pattern_lo);
-                    pattern_hi = flip_byte(pattern_hi);
-                }
+            if high {
+                self.sprite_pattern_hi[index] = pattern;
+            } else {
+                self.sprite_pattern_lo[index] = pattern;
+            }
+        } else {
+            self.fetch(Address::new(self.dummy_sprite_addr() + offset));
+        }
+        self.set_fetch(PpuFetch::Background);
+    }
 
-                self.sprite_pattern_lo[i] = pattern_lo;
-                self.sprite_pattern_hi[i] = pattern_hi;
+    fn load_foreground_data(&mut self) {
+        if self.cycle == SPRITE_EVALUATION_CYCLE {
+            if self.scanline >= 0 {
+                self.evaluate_sprites();
+            } else {
+                // Nothing is drawn on the first line, but the pattern fetches still happen
+                self.sprite_count = 0;
             }
+        } else if (self.cycle > SPRITE_EVALUATION_CYCLE) && (self.cycle <= SPRITE_FETCH_END_CYCLE) {
+            // Each sprite slot takes 8 dots starting at dot 257, the pattern bytes are
+            // read on the fifth and seventh dot. Mappers watching A12 depend on this timing.
+            let index = ((self.cycle - 257) / 8) as usize;
+            match (self.cycle - 257) % 8 {
+                4 => self.fetch_sprite_pattern(index, false),
+                6 => self.fetch_sprite_pattern(index, true),
+                _ => {}
+            }
+        }
+    }
+
+    #[inline]
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// PAL consoles have a much longer vertical blanking period
+    #[inline]
+    fn max_scanline(&self) -> i16 {
+        match self.region {
+            Region::Ntsc => MAX_SCANLINE_NTSC,
+            Region::Pal | Region::Dendy => MAX_SCANLINE_PAL,
+        }
+    }
+
+    #[inline]
+    fn vblank_line(&self) -> i16 {
+        match self.region {
+            Region::Ntsc | Region::Pal => VBLANK_LINE,
+            Region::Dendy => VBLANK_LINE_DENDY,
         }
     }
 
     fn clock_one(&mut self) {
         if self.scanline < VBLANK_LINE {
-            if (self.scanline == 0) && (self.cycle == 0) {
-                self.cycle = 1; // "Odd frame" skip
+            if (self.scanline == 0) && (self.cycle == 0) && (self.region == Region::Ntsc) {
+                self.cycle = 1; // "Odd frame" skip, PAL and Dendy consoles don't have it
             }
 
             if (self.scanline == -1) && (self.cycle == 1) {
@@ -704,7 +821,7 @@ impl<'a> Ppu2C02<'a> {
             self.load_foreground_data();
         }
 
-        if (self.scanline == (VBLANK_LINE + 1)) && (self.cycle == 1) {
+        if (self.scanline == (self.vblank_line() + 1)) && (self.cycle == 1) {
             self.status.insert(PpuStatus::VERTICAL_BLANK);
             if self.control.contains(PpuControl::ENABLE_NMI) {
                 self.nmi = true;
@@ -800,11 +917,9 @@ impl<'a> Ppu2C02<'a> {
         }
 
         self.cycle += 1;
+        self.total_cycles += 1;
 
-        if self
-            .mask
-            .intersects(PpuMask::RENDER_BACKGROUND | PpuMask::RENDER_SPRITES)
-        {
+        if self.rendering_enabled() {
             if (self.cycle == 260) && (self.scanline < VBLANK_LINE) {
                 if let Some(cartridge) = &self.cartridge {
                     let mut cart = cartridge.borrow_mut();
@@ -817,18 +932,41 @@ impl<'a> Ppu2C02<'a> {
         if self.cycle > MAX_CYCLE {
             self.cycle = 0;
             self.scanline += 1;
-            if self.scanline > MAX_SCANLINE {
+            if self.scanline > self.max_scanline() {
                 self.scanline = -1;
                 std::mem::swap(&mut self.back_buffer, &mut self.front_buffer);
             }
         }
     }
 
+    /// Fills OAM the way it is found after power on, registers are cleared by `reset`
+    #[inline]
+    pub fn fill_oam(&mut self, pattern: RamInit, salt: u64) {
+        self.oam.fill(pattern, salt);
+    }
+
     #[inline]
     pub fn dma_write(&mut self, addr: Wrapping<u8>, data: Word) {
         self.oam.write(addr, data);
     }
 }
+impl<'a> LightSource for Ppu2C02<'a> {
+    #[inline]
+    fn beam_position(&self) -> (isize, isize) {
+        ((self.cycle as isize) - 1, self.scanline as isize)
+    }
+
+    fn brightness(&self, x: isize, y: isize) -> u8 {
+        if (x >= 0) && (y >= 0) && (x < SCREEN_WIDTH as isize) && (y < SCREEN_HEIGHT as isize) {
+            // The back buffer holds the frame that is currently being drawn
+            let color = self.back_buffer.get_pixel(x as usize, y as usize);
+            let sum: u32 = color.channels[..3].iter().map(|&c| c as u32).sum();
+            (sum / 3) as u8
+        } else {
+            0
+        }
+    }
+}
 impl<'a> BusComponent<cpu::cpu6502::Address, cpu::cpu6502::Word> for Ppu2C02<'a> {
     #[inline]
     fn read_range(&self) -> Option<AddressRange<cpu::cpu6502::Address>> {
@@ -858,7 +996,10 @@ impl<'a> BusComponent<cpu::cpu6502::Address, cpu::cpu6502::Word> for Ppu2C02<'a>
             ADDR_PPU_DATA => {
                 // Everything except palette data is buffered one cycle
                 let mut tmp = self.ppu_data_buffer;
+                self.set_fetch(PpuFetch::Cpu);
+                self.notify_address(Address::new(self.vram_addr.value));
                 self.ppu_data_buffer = self.read_bus(Address::new(self.vram_addr.value));
+                self.set_fetch(PpuFetch::Background);
                 if self.vram_addr.value >= 0x3F00 {
                     tmp = self.ppu_data_buffer;
                 }
@@ -873,6 +1014,11 @@ impl<'a> BusComponent<cpu::cpu6502::Address, cpu::cpu6502::Word> for Ppu2C02<'a>
     }
 
     fn write(&mut self, addr: cpu::cpu6502::Address, data: cpu::cpu6502::Word) {
+        // Some mappers snoop the PPU registers
+        if let Some(cartridge) = &self.cartridge {
+            cartridge.borrow_mut().on_ppu_register_write(addr, data);
+        }
+
         match addr {
             ADDR_CONTROL => {
                 self.control = PpuControl::from_bits_truncate(data.0);
@@ -902,6 +1048,7 @@ impl<'a> BusComponent<cpu::cpu6502::Address, cpu::cpu6502::Word> for Ppu2C02<'a>
                     self.tram_addr.value = (self.tram_addr.value & 0xFF00) | (data.0 as u16);
                     self.tram_addr.update_subfields();
                     self.vram_addr = self.tram_addr;
+                    self.notify_address(Address::new(self.vram_addr.value));
                 } else {
                     self.tram_addr.value =
                         (self.tram_addr.value & 0x00FF) | (((data.0 & 0x3F) as u16) << 8);
@@ -910,7 +1057,10 @@ impl<'a> BusComponent<cpu::cpu6502::Address, cpu::cpu6502::Word> for Ppu2C02<'a>
                 self.ppu_addr_latch = !self.ppu_addr_latch;
             }
             ADDR_PPU_DATA => {
+                self.set_fetch(PpuFetch::Cpu);
+                self.notify_address(Address::new(self.vram_addr.value));
                 self.write_bus(Address::new(self.vram_addr.value), data);
+                self.set_fetch(PpuFetch::Background);
                 // Auto-increment
                 self.vram_addr.value +=
                     select(self.control.contains(PpuControl::INCREMENT_MODE), 32, 1);
@@ -0,0 +1,102 @@
+#!/usr/bin/env python3
+"""Generates res/nesdb.txt from the XML export of NesCartDB.
+
+Usage: gen_nesdb.py nescarta.xml > res/nesdb.txt
+
+Only the CRC32 of PRG and CHR ROM together is used as the key, which is what
+NesCartDB stores as the cartridge CRC. Submappers are not part of the export
+and are written as -, so the header keeps deciding them.
+"""
+
+import sys
+import xml.etree.ElementTree as ElementTree
+
+HEADER = """\
+# Known games, matched by the CRC32 of their PRG and CHR ROM (without header)
+# Regenerate with tools/gen_nesdb.py from the NesCartDB XML export (https://nescartdb.com)
+# CRC32;mapper;submapper (- to keep the header);mirroring (H, V, 4 or - to keep the header);battery (0/1);PRG RAM in KiB (- to keep the header);CHR RAM in KiB (0 to keep the header);region (NTSC, PAL, Dendy);name"""
+
+
+def kib(size):
+    """Sizes are given as e.g. "8k" """
+    return int(size.rstrip("kK") or 0)
+
+
+def region(system):
+    if system.startswith("NES-PAL"):
+        return "PAL"
+    if system == "Dendy":
+        return "Dendy"
+    return "NTSC"
+
+
+# TQROM has CHR RAM next to its CHR ROM
+CHR_ROM_AND_RAM_MAPPERS = {"119"}
+
+
+def mirroring(board, has_chr_rom):
+    # Extra VRAM next to CHR ROM is used for four screen nametables, unless it is CHR RAM
+    if (
+        has_chr_rom
+        and board.get("mapper") not in CHR_ROM_AND_RAM_MAPPERS
+        and board.find("vram") is not None
+    ):
+        return "4"
+
+    pad = board.find("pad")
+    if pad is None:
+        # The mapper controls mirroring
+        return "-"
+    if pad.get("v") == "1":
+        return "V"
+    if pad.get("h") == "1":
+        return "H"
+    return "-"
+
+
+def entry(game, cartridge):
+    board = cartridge.find("board")
+    if board is None or board.get("mapper") is None:
+        return None
+
+    has_chr_rom = board.find("chr") is not None
+    prg_ram = sum(kib(wram.get("size", "0")) for wram in board.findall("wram"))
+    chr_ram = 0
+    if not has_chr_rom or board.get("mapper") in CHR_ROM_AND_RAM_MAPPERS:
+        chr_ram = sum(kib(vram.get("size", "0")) for vram in board.findall("vram"))
+    battery = any(element.get("battery") == "1" for element in board.iter())
+
+    fields = [
+        cartridge.get("crc").upper(),
+        board.get("mapper"),
+        "-",
+        mirroring(board, has_chr_rom),
+        "1" if battery else "0",
+        str(prg_ram),
+        str(chr_ram),
+        region(cartridge.get("system", "")),
+        # The separator can't be escaped
+        game.get("name").replace(";", ","),
+    ]
+    return ";".join(fields)
+
+
+def main():
+    if len(sys.argv) != 2:
+        sys.exit(__doc__.strip())
+
+    entries = {}
+    for game in ElementTree.parse(sys.argv[1]).getroot().iter("game"):
+        for cartridge in game.findall("cartridge"):
+            line = entry(game, cartridge)
+            # Revisions with identical ROMs are listed more than once, the first one wins
+            if line is not None:
+                entries.setdefault(cartridge.get("crc").upper(), line)
+
+    print(HEADER)
+    for crc in sorted(entries):
+        print(entries[crc])
+
+
+if __name__ == "__main__":
+    main()